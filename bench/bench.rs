@@ -0,0 +1,168 @@
+// Intcode interpreter benchmarks; build with optimizations, e.g.
+// `rustc -O bench/bench.rs -o intcode-bench` from the repository root.
+
+#[allow(dead_code, unused_imports)]
+#[path = "../intcode/mod.rs"]
+mod intcode;
+
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use intcode::{Machine, Memory, MemoryKind, State, assemble, parse_program};
+
+
+// sums i*i for i = N-1 down to 0, keeping the counter relative to the base
+const SQUARES_SOURCE: &str = "
+		arb #counter
+		in [rb]
+loop:	add [rb], #-1, [rb]
+		mul [rb], [rb], [square]
+		add [total], [square], [total]
+		lt [rb], #1, [done]
+		jz [done], #loop
+		out [total]
+		hlt
+counter:	data 0
+square:	data 0
+total:	data 0
+done:	data 0
+";
+
+// bumps N cells spread SCATTER_STRIDE apart, ending far past the image
+const SCATTER_STRIDE: usize = 99_991;
+
+fn scatter_source() -> String {
+	format!("
+		in [count]
+loop:	arb #{}
+		add [rb], #1, [rb]
+		add [count], #-1, [count]
+		jnz [count], #loop
+		hlt
+count:	data 0
+", SCATTER_STRIDE)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+	Step,
+	Fast,
+	Sparse,
+}
+
+//-----------------------------------------------------------------------------
+
+fn time<F: FnMut() -> u64>(mut f: F) -> (Duration, u64) {
+	let start = Instant::now();
+	let instructions = f();
+
+	(start.elapsed(), instructions)
+}
+
+fn report(name: &str, (a_name, a): (&str, (Duration, u64)), (b_name, b): (&str, (Duration, u64))) {
+	let per_instruction = |(elapsed, count): (Duration, u64)| {
+		elapsed.as_nanos() as f64 / count.max(1) as f64
+	};
+
+	println!(
+		"{:<24} {:>12} instr  {} {:>8.2?} ({:.2} ns/instr)  \
+		{} {:>8.2?} ({:.2} ns/instr)  ratio {:.2}x",
+		name, a.1,
+		a_name, a.0, per_instruction(a),
+		b_name, b.0, per_instruction(b),
+		a.0.as_secs_f64() / b.0.as_secs_f64(),
+	);
+}
+
+fn machine(program: Vec<i64>, mode: Mode) -> Machine {
+	match mode {
+		Mode::Sparse => Machine::from_memory(Memory::with_kind(program, MemoryKind::Sparse)),
+		_ => Machine::new(program),
+	}
+}
+
+fn run_to_halt(machine: &mut Machine, mode: Mode) -> Vec<i64> {
+	let mut outputs = Vec::<i64>::new();
+	loop {
+		let state = match mode {
+			Mode::Fast => machine.run_fast(),
+			_ => machine.run(),
+		}.expect("benchmark program failed");
+
+		match state {
+			State::Produced(value) => outputs.push(value),
+			State::Halted | State::Exited(_) => return outputs,
+			state => panic!("unexpected state {:?}", state),
+		}
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+fn bench_noun_verb(code: &[i64], mode: Mode) -> u64 {
+	let mut instructions = 0u64;
+
+	for noun in 0..=99 {
+		for verb in 0..=99 {
+			let mut program = code.to_vec();
+			program[1] = noun;
+			program[2] = verb;
+			let mut machine = machine(program, mode);
+
+			run_to_halt(&mut machine, mode);
+			instructions += machine.instructions_executed();
+		}
+	}
+
+	instructions
+}
+
+fn bench_with_input(program: &[i64], input: i64, mode: Mode) -> (u64, Vec<i64>) {
+	let mut machine = machine(program.to_vec(), mode);
+	machine.push_input(input);
+	let outputs = run_to_halt(&mut machine, mode);
+
+	(machine.instructions_executed(), outputs)
+}
+
+fn main() {
+	let day2 = parse_program(include_str!("../day2/edited-input.txt"))
+		.expect("invalid day2 program");
+
+	let squares = assemble(SQUARES_SOURCE).expect("invalid benchmark source");
+	let n = 1_000_000;
+	assert_eq!(
+		bench_with_input(&squares, n, Mode::Step), bench_with_input(&squares, n, Mode::Fast),
+		"fast path disagrees with the interpreter"
+	);
+
+	let scatter = assemble(&scatter_source()).expect("invalid benchmark source");
+	let cells = ((Memory::DEFAULT_LIMIT - 1) / SCATTER_STRIDE) as i64;
+
+	report(
+		"day2 noun/verb sweep",
+		("step", time(|| bench_noun_verb(&day2, Mode::Step))),
+		("fast", time(|| bench_noun_verb(&day2, Mode::Fast))),
+	);
+	report(
+		"sum of squares",
+		("step", time(|| bench_with_input(&squares, n, Mode::Step).0)),
+		("fast", time(|| bench_with_input(&squares, n, Mode::Fast).0)),
+	);
+
+	report(
+		"day2 noun/verb sweep",
+		("dense", time(|| bench_noun_verb(&day2, Mode::Step))),
+		("sparse", time(|| bench_noun_verb(&day2, Mode::Sparse))),
+	);
+	report(
+		"sum of squares",
+		("dense", time(|| bench_with_input(&squares, n, Mode::Step).0)),
+		("sparse", time(|| bench_with_input(&squares, n, Mode::Sparse).0)),
+	);
+	report(
+		"scattered writes",
+		("dense", time(|| bench_with_input(&scatter, cells, Mode::Step).0)),
+		("sparse", time(|| bench_with_input(&scatter, cells, Mode::Sparse).0)),
+	);
+}
@@ -1,88 +1,61 @@
-use std::vec::Vec;
-
-fn exec_code(code: &mut Vec<usize>) {
-	let mut code_pos = 0usize;
-
-	while code_pos < code.len() {
-		let op = code[code_pos];
-
-		match op {
-			99usize => break,
-			1usize => {
-				let arg1pos = code[code_pos+1];
-				let arg2pos = code[code_pos+2];
-				let respos = code[code_pos+3];
-
-				code[respos] = code[arg1pos] + code[arg2pos];
-				code_pos += 4;
-			},
-			2usize => {
-				let arg1pos = code[code_pos+1];
-				let arg2pos = code[code_pos+2];
-				let respos = code[code_pos+3];
-
-				code[respos] = code[arg1pos] * code[arg2pos];
-				code_pos += 4;
-			},
-			_ => panic!("invalid opcode {:?}", op),
-		}
+#[allow(dead_code, unused_imports)]
+#[path = "../intcode/mod.rs"]
+mod intcode;
+
+use intcode::{Search, load_program, solve_noun_verb, symbolic_result};
+
+
+// far more than any sane noun/verb pair needs; keeps bad pairs from hanging
+const INSTRUCTION_BUDGET: u64 = 100_000;
+
+fn find_noun_verb(code: &[i64], expected_result: i64) -> [i64; 2] {
+	match symbolic_result(code) {
+		Ok(result) => {
+			println!("address 0 = {}", result);
+			let (noun, verb) = solve_noun_verb(&result, expected_result, 0..=99)
+				.expect("no solution found");
+			return [noun, verb];
+		},
+		Err(error) => println!("falling back to brute force: {}", error),
+	};
+
+	let mut search = Search::new(code.to_vec());
+	// the noun and verb are at addresses 1 and 2
+	for addr in 1..=2 {
+		search.add_patch(addr, 0..=99).expect("patch out of bounds");
 	}
-}
-
-fn find_noun_verb(code: &Vec<usize>, expected_result: usize) -> [usize; 2] {
-	for noun in 0usize..=99usize {
-		for verb in 0usize..=99usize {
-			let mut new_code = code.clone();
-			new_code[1] = noun;
-			new_code[2] = verb;
-
-			exec_code(&mut new_code);
+	search.set_instruction_budget(Some(INSTRUCTION_BUDGET));
 
-			if new_code[0] == expected_result {
-				return [noun, verb];
-			}
-		}
-	}
+	// candidates that crash the program simply aren't solutions
+	let found = search.find_first(|outcome| {
+		outcome.state.is_ok() && outcome.memory[0] == expected_result
+	}).expect("search space too large").expect("no solution found");
 
-	panic!("no solution found");
+	[found[0], found[1]]
 }
 
 //------------------------------------------------------------------
 
-fn parse_code_string(output: &mut Vec<usize>, input: &str) {
-	for word in input.split(",") {
-		output.push(word.trim().parse::<usize>().expect("invalid input string"));
-	}
-}
-
-fn print_code(code: &Vec<usize>) {
-	let iter = code.iter();
-
-	/*match iter.next() {
-		Some(expr) => print!("{:?}", expr),
-		None => {},
-	}
-	*/
-	for ch in iter {
-		print!("{:?},", ch);
+fn main() {
+	// the program comes from the file given with --program, as for day5
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let path = args.iter().position(|arg| arg == "--program").and_then(|i| args.get(i+1));
+	if path.is_none() {
+		println!("Enter program code below:");
 	}
+	let code = match load_program(path.map(|path| path.as_str())) {
+		Ok(code) => code,
+		Err(error) => {
+			println!("{}", error);
+			std::process::exit(1);
+		}
+	};
 
-	println!("");
-}
-
-fn main() {
-	println!("Enter program code below:");
-	let mut code = Vec::<usize>::new();
 	let mut buffer = String::new();
-	std::io::stdin().read_line(&mut buffer).expect("invalid code");
-	parse_code_string(&mut code, &buffer);
-	//exec_code(&mut code);
-	buffer.clear();
-	
 	println!("Enter expected result at address 0:");
 	std::io::stdin().read_line(&mut buffer).expect("no expected result found");
-	let expected_result = buffer.trim().parse::<usize>().expect("invalid expected result");
+	let expected_result = buffer.trim().parse::<i64>().expect("invalid expected result");
 	let desired_input = find_noun_verb(&code, expected_result);
 
 	println!("{:?}{:?}", desired_input[0], desired_input[1]);
-}
\ No newline at end of file
+}
@@ -1,272 +1,249 @@
-use std::convert::{TryFrom, TryInto};
-use std::cmp::{min, Ordering};
-use std::ops::{RangeBounds, Bound::*};
+#[allow(dead_code, unused_imports)]
+#[path = "../intcode/mod.rs"]
+mod intcode;
+
+use std::io::Read;
 use std::vec::Vec;
 
+use intcode::{
+	Amplifiers, Arithmetic, BigInt, Ascii, CameraFrame, Cell, Cfg, Debugger, Error, Grid, Memory, NetEvent, Network,
+	PaintRobot, State, TileScreen, Wiring, Machine, Terminal, TraceFormat, Tracer,
+	assemble, load_program, load_words, print_listing, to_code_string,
+};
 
-#[derive(Debug, Clone, Copy)]
-struct Digits(u32);
 
-impl Digits {
-	const NO_OF_DIGITS: u8 = 9;
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+	args.iter().position(|arg| arg == name).and_then(|i| args.get(i+1))
+}
 
-	pub fn subdigits<R: RangeBounds<u8>>(&self, index: R) -> Self {
-		let lbound = match index.start_bound() {
-			Unbounded => 0,
-			Included(&n) => n,
-			Excluded(&n) => n+1
-		};
-		let ubound = match index.end_bound() {
-			Unbounded => Self::NO_OF_DIGITS,
-			Included(&n) => min(Self::NO_OF_DIGITS, n+1),
-			Excluded(&n) => min(Self::NO_OF_DIGITS, n),
-		};
+// Prints `grid` and saves it to any --ppm/--png files asked for.
+fn show_grid<G, C>(args: &[String], grid: &Grid, glyph: G, color: C)
+where
+	G: Fn(Option<i64>) -> char,
+	C: Fn(Option<i64>) -> [u8; 3] + Copy,
+{
+	print!("{}", grid.render(glyph));
 
-		Self::from(
-			if lbound >= ubound {
-				0_u32
-			} else {
-				(self.0 / 10_u32.pow(lbound.into()))
-				% 10_u32.pow((ubound-lbound).into())
-			}
-		)
+	const SCALE: usize = 8;
+	if let Some(path) = option_value(args, "--ppm") {
+		let mut file = std::fs::File::create(path).expect("cannot create image file");
+		grid.write_ppm(&mut file, color, SCALE).expect("failed to write image");
 	}
-}
-
-impl From<u32> for Digits {
-	fn from(value: u32) -> Self {
-		Self(value)
+	if let Some(path) = option_value(args, "--png") {
+		let mut file = std::fs::File::create(path).expect("cannot create image file");
+		grid.write_png(&mut file, color, SCALE).expect("failed to write image");
 	}
 }
 
-impl Into<u32> for Digits {
-	fn into(self) -> u32 {
-		self.0
+// Sets up a machine from the memory and execution options.
+fn build_machine<C: Cell>(args: &[String], program: Vec<C>) -> Machine<C> {
+	let mut memory = match args.iter().any(|arg| arg == "--sparse") {
+		true => Memory::sparse(program),
+		false => Memory::new(program),
+	};
+	if let Some(limit) = option_value(args, "--memory-limit") {
+		memory.set_limit(limit.parse::<usize>().expect("invalid memory limit"));
 	}
-}
-
-//-----------------------------------------------------------------------------
-
-fn parse_code_string(output: &mut Vec<i32>, input: &str) {
-	for word in input.split(",") {
-		output.push(word.trim().parse::<i32>().expect("invalid input string"));
+	let mut machine = Machine::from_memory(memory);
+	machine.set_instruction_budget(option_value(args, "--budget").map(
+		|n| n.parse::<u64>().expect("invalid instruction budget")
+	));
+	machine.set_loop_detection(args.iter().any(|arg| arg == "--detect-loops"));
+	if args.iter().any(|arg| arg == "--wrapping") {
+		machine.set_arithmetic(Arithmetic::Wrapping);
 	}
-}
 
-//-----------------------------------------------------------------------------
-
-#[derive(Debug)]
-enum ErrorCode {
-	ParamMode(u32),
-	ProgramPosition(usize),
-	PositionValue(i32),
+	machine
 }
 
-
-#[derive(Debug)]
-enum ParameterRef {
-	Position(usize),
-	Immediate(usize),
+fn debug<C: Cell>(machine: Machine<C>) {
+	let stdin = std::io::stdin();
+	Debugger::new(machine)
+		.repl(stdin.lock(), std::io::stdout())
+		.expect("debugger i/o failed");
 }
 
-impl ParameterRef {
-	fn from_pos_mode(pos: usize, mode: u32) -> Result<ParameterRef, ErrorCode> {
-		match mode {
-			0 => Ok(Self::Position(pos)),
-			1 => Ok(Self::Immediate(pos)),
-			m => Err(ErrorCode::ParamMode(m))
-		}
-	}
-	fn deref<'a>(&self, program: &'a Vec<i32>) -> Result<&'a i32, ErrorCode> {
-		
-		match self {
-			Self::Immediate(pos) => program.get(*pos).ok_or(
-				ErrorCode::ProgramPosition(*pos)
-			),
-			Self::Position(pos) => {
-				let value = *program.get(*pos).ok_or(
-					ErrorCode::ProgramPosition(*pos)
-				)?;
-				let value_pos = usize::try_from(value).ok().ok_or(
-					ErrorCode::PositionValue(value)
-				)?;
+// Runs against the terminal, tracing if asked to.
+fn run_terminal<C: Cell>(args: &[String], machine: &mut Machine<C>)
+-> Result<State<C>, Error<C>> {
+	let trace = option_value(args, "--trace").map(|path| (path, TraceFormat::JsonLines))
+		.or(option_value(args, "--trace-binary").map(|path| (path, TraceFormat::Binary)));
 
-				program.get(value_pos).ok_or(
-					ErrorCode::ProgramPosition(value_pos)
-				)
-			},
-		}
-	}	
+	match trace {
+		Some((path, format)) => {
+			let file = std::fs::File::create(path).expect("cannot create trace file");
+			let mut tracer = Tracer::new(std::io::BufWriter::new(file), format);
+			let result = tracer.run_with(machine, &mut Terminal, &mut Terminal);
+			if let Err(error) = tracer.finish() {
+				println!("failed to write trace: {}", error);
+				std::process::exit(1);
+			}
+			result
+		},
+		None => machine.run_with(&mut Terminal, &mut Terminal),
+	}
 }
 
-#[derive(Debug)]
-enum ParameterMutRef {
-	Position(usize),
+fn report<C: Cell>(result: Result<State<C>, Error<C>>) {
+	if let Err(error) = result {
+		println!("program failed: {}", error);
+		std::process::exit(1);
+	}
 }
 
-impl ParameterMutRef {
-	fn from_pos_mode(pos: usize, mode: u32) -> Result<ParameterMutRef, ErrorCode> {
-		match mode {
-			0 => Ok(Self::Position(pos)),
-			m => Err(ErrorCode::ParamMode(m))
+fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+
+	if args.iter().any(|arg| arg == "--assemble") {
+		println!("Enter assembly source below (end with EOF):");
+		let mut source = String::new();
+		std::io::stdin().read_to_string(&mut source).expect("invalid source");
+
+		match assemble(&source) {
+			Ok(program) => println!("{}", to_code_string(&program)),
+			Err(error) => {
+				println!("assembly failed: {}", error);
+				std::process::exit(1);
+			}
 		}
+		return;
 	}
-	fn deref<'a>(&self, program: &'a mut Vec<i32>) -> Result<&'a mut i32, ErrorCode> {
-		let Self::Position(pos) = self;
-
-		let value = *program.get(*pos).ok_or(
-			ErrorCode::ProgramPosition(*pos)
-		)?;
-		let value_pos = usize::try_from(value).ok().ok_or(
-			ErrorCode::PositionValue(value)
-		)?;
-
-		program.get_mut(value_pos).ok_or(
-			ErrorCode::ProgramPosition(value_pos)
-		)
-	}	
-}
-
-
-fn get_param_ref<'a>(
-	program: &'a Vec<i32>, pos: usize, offset: u8
-) -> &'a i32 {
-	let modes = Digits::from(u32::try_from(program[pos]).unwrap());
-
-	ParameterRef
-	::from_pos_mode(
-		pos+1+usize::from(offset),
-		modes.subdigits(2+offset..3+offset).into()
-	).unwrap()
-	.deref(program).unwrap()
-}
-
-fn get_param_mutref<'a>(
-	program: &'a mut Vec<i32>, pos: usize, offset: u8
-) -> &'a mut i32 {
-	let modes = Digits::from(u32::try_from(program[pos]).unwrap());
-
-	ParameterMutRef
-	::from_pos_mode(
-		pos+1+usize::from(offset),
-		modes.subdigits(2+offset..3+offset).into()
-	).unwrap()
-	.deref(program).unwrap()
-}
-
-//-----------------------------------------------------------------------------
 
-#[derive(Debug)]
-enum OpInstruction {
-	Add,
-	Multiply,
-	Input,
-	Output,
-	Jump(bool),
-	Compare(Ordering),
-
-	Terminate,
-}
+	let path = option_value(&args, "--program").map(|path| path.as_str());
+	if path.is_none() {
+		println!("Enter program code below:");
+	}
 
-impl OpInstruction {
-	fn from_opcode(opcode: u32) -> Result<OpInstruction, String> {
-		match Digits::from(opcode).subdigits(..2).into() {
-			99u32 => Ok(Self::Terminate),
-			1u32 => Ok(Self::Add),
-			2u32 => Ok(Self::Multiply),
-			3u32 => Ok(Self::Input),
-			4u32 => Ok(Self::Output),
-			5u32 => Ok(Self::Jump(true)),
-			6u32 => Ok(Self::Jump(false)),
-			7u32 => Ok(Self::Compare(Ordering::Less)),
-			8u32 => Ok(Self::Compare(Ordering::Equal)),
-			n => Err(format!("invalid opcode '{:?}'", n))
+	if args.iter().any(|arg| arg == "--bigint") {
+		let program: Vec<BigInt> = match load_words(path) {
+			Ok(program) => program,
+			Err(error) => {
+				println!("{}", error);
+				std::process::exit(1);
+			}
+		};
+		let mut machine = build_machine(&args, program);
+		match args.iter().any(|arg| arg == "--debug") {
+			true => debug(machine),
+			false => report(run_terminal(&args, &mut machine)),
 		}
+		return;
 	}
-}
-
-//-----------------------------------------------------------------------------
-
-fn exec_program(program: &mut Vec<i32>) {
-	let mut pos = 0usize;
-
-	while pos < program.len() {
-		let op_modes = u32::try_from(program[pos]).unwrap();
+	let program = match load_program(path) {
+		Ok(program) => program,
+		Err(error) => {
+			println!("{}", error);
+			std::process::exit(1);
+		}
+	};
 
-		match OpInstruction::from_opcode(op_modes).unwrap() {
-			OpInstruction::Add => {
-				*get_param_mutref(program, pos, 2)
-				= get_param_ref(program, pos, 0)
-				+ get_param_ref(program, pos, 1);
+	if args.iter().any(|arg| arg == "--disassemble") {
+		print_listing(&program);
+		return;
+	}
+	if args.iter().any(|arg| arg == "--cfg") {
+		print!("{}", Cfg::build(&program).to_dot());
+		return;
+	}
+	if args.iter().any(|arg| arg == "--debug") {
+		debug(build_machine(&args, program));
+		return;
+	}
 
-				pos += 4;
-			}
-			OpInstruction::Multiply => {
-				*get_param_mutref(program, pos, 2)
-				= get_param_ref(program, pos, 0)
-				* get_param_ref(program, pos, 1);
+	if let Some(settings) = option_value(&args, "--amplify") {
+		let settings: Vec<i64> = settings.split(',')
+			.map(|s| s.trim().parse::<i64>().expect("invalid phase setting"))
+			.collect();
+		let wiring = match args.iter().any(|arg| arg == "--feedback") {
+			true => Wiring::Feedback,
+			false => Wiring::Series,
+		};
 
-				pos += 4;
+		let amplifiers = Amplifiers::from_machine(build_machine(&args, program), wiring);
+		match amplifiers.best_phases(&settings) {
+			Ok((phases, signal)) => println!("phases {:?} give signal {}", phases, signal),
+			Err(error) => {
+				println!("amplifiers failed: {}", error);
+				std::process::exit(1);
 			}
-			OpInstruction::Input => {
-				println!("Enter in an input value");
-				let mut buffer = String::new();
-				std::io::stdin().read_line(&mut buffer).expect("invalid code");
-				let input_value = buffer.trim().parse::<i32>().expect(
-					"invalid input string"
-				);
-
-				*get_param_mutref(program, pos, 0) = input_value;
+		}
+		return;
+	}
 
-				pos += 2;
-			}
-			OpInstruction::Output => {
-				println!("{:?}", get_param_ref(program, pos, 0));
+	if let Some(size) = option_value(&args, "--network") {
+		let size = size.parse::<usize>().expect("invalid network size");
+		let mut network = Network::from_machine(build_machine(&args, program), size);
 
-				pos += 2;
-			}
-			OpInstruction::Jump(trigger) => {
-				if trigger == (0 !=
-					*get_param_ref(program, pos, 0)
-				) {
-					pos = usize::try_from(
-						*get_param_ref(program, pos, 1)
-					).unwrap();
-				} else {
-					pos += 3;
+		let mut seen_nat = false;
+		let mut last_wake = None;
+		let result = network.run_until(|event| match event {
+			NetEvent::ToNat(packet) => {
+				if !seen_nat {
+					println!("first packet to NAT: {:?}", packet);
 				}
+				seen_nat = true;
+				false
+			},
+			NetEvent::NatWake(packet) => {
+				let repeated = last_wake == Some(packet.y);
+				last_wake = Some(packet.y);
+				repeated
+			},
+			_ => false,
+		});
+		match result {
+			Ok(event) => println!("first repeated NAT wake-up: {:?}", event),
+			Err(error) => {
+				println!("network failed: {}", error);
+				std::process::exit(1);
 			}
-			OpInstruction::Compare(trigger) => {
-				*get_param_mutref(program, pos, 2)
-					= (trigger == get_param_ref(program, pos, 0).cmp(
-						get_param_ref(program, pos, 1)
-					)) as i32;
-
-				pos += 4;
-			}
-			OpInstruction::Terminate => break,
 		}
+		return;
 	}
-}
 
-//------------------------------------------------------------------
-fn print_code(code: &Vec<i32>) {
-	let iter = code.iter();
+	let mut machine = build_machine(&args, program);
 
-	for ch in iter {
-		print!("{:?},", ch);
-	}
+	if args.iter().any(|arg| arg == "--ascii") {
+		let stdin = std::io::stdin();
+		let mut ascii = Ascii::new(stdin.lock(), std::io::stdout());
+		let transcript = option_value(&args, "--transcript");
+		if transcript.is_some() {
+			ascii.record_transcript();
+		}
 
-	println!("");
-}
+		let result = ascii.run(&mut machine);
+		if let (Some(path), Some(text)) = (transcript, ascii.transcript()) {
+			std::fs::write(path, text).expect("failed to write transcript");
+		}
+		if let Err(error) = result {
+			println!("program failed: {}", error);
+			std::process::exit(1);
+		}
+		return;
+	}
 
-fn main() {
-	println!("Enter program code below:");
-	let mut program = Vec::<i32>::new();
-	let mut buffer = String::new();
-	std::io::stdin().read_line(&mut buffer).expect("invalid program");
-	parse_code_string(&mut program, &buffer);
-	buffer.clear();
-	
-	exec_program(&mut program);
+	let result = if args.iter().any(|arg| arg == "--paint") {
+		let mut robot = PaintRobot::new();
+		let result = robot.run(&mut machine);
+		println!("{} panels painted", robot.grid.len());
+		show_grid(&args, &robot.grid, PaintRobot::glyph, PaintRobot::color);
+		result
+	} else if args.iter().any(|arg| arg == "--screen") {
+		let mut screen = TileScreen::new();
+		let result = machine.run_with(&mut Terminal, &mut screen);
+		show_grid(&args, &screen.grid, TileScreen::glyph, TileScreen::color);
+		if let Some(score) = screen.score {
+			println!("score: {}", score);
+		}
+		result
+	} else if args.iter().any(|arg| arg == "--camera") {
+		let mut camera = CameraFrame::new();
+		let result = machine.run_with(&mut Terminal, &mut camera);
+		show_grid(&args, camera.latest(), CameraFrame::glyph, CameraFrame::color);
+		result
+	} else {
+		run_terminal(&args, &mut machine)
+	};
+
+	report(result);
 }
@@ -0,0 +1,232 @@
+// Differential and robustness fuzzing for the shared interpreter; build with
+// `rustc -O fuzz/fuzz.rs -o intcode-fuzz` from the repository root and run
+// as `intcode-fuzz [ITERATIONS] [SEED]`.
+//
+// day2 no longer has its own interpreter, so the differential target checks
+// the machine against `reference_exec`, a copy of day2's original
+// `exec_code` (opcodes 1, 2 and 99 only). The robustness target feeds
+// arbitrary words to `run`, `run_fast` and `run` over sparse memory,
+// checking that none of them panics and that they agree.
+
+#[allow(dead_code, unused_imports)]
+#[path = "../intcode/mod.rs"]
+mod intcode;
+
+use std::convert::TryFrom;
+use std::panic::{self, AssertUnwindSafe};
+use std::vec::Vec;
+
+use intcode::{Error, ErrorKind, Machine, Memory, MemoryKind, State, to_code_string};
+
+
+// keeps runaway programs short and memory growth small
+const INSTRUCTION_BUDGET: u64 = 10_000;
+const MEMORY_LIMIT: usize = 1 << 16;
+
+// xorshift64*; good enough to spread test cases around
+struct Rng(u64);
+
+impl Rng {
+	fn new(seed: u64) -> Self {
+		Rng(seed.max(1))
+	}
+
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 >> 12;
+		self.0 ^= self.0 << 25;
+		self.0 ^= self.0 >> 27;
+		self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+	}
+
+	fn below(&mut self, n: u64) -> u64 {
+		self.next() % n
+	}
+
+	fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+		&items[self.below(items.len() as u64) as usize]
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug, PartialEq, Eq)]
+enum RefError {
+	InvalidOpcode,
+	// an address outside the program, which day2 never had to handle
+	OutOfBounds,
+	Overflow,
+}
+
+// day2's original `exec_code`, reporting the cases it used to panic on.
+fn reference_exec(code: &mut Vec<i64>) -> Result<(), RefError> {
+	let mut code_pos = 0usize;
+	let addr = |code: &Vec<i64>, pos: usize| {
+		code.get(pos)
+			.and_then(|&value| usize::try_from(value).ok())
+			.filter(|&addr| addr < code.len())
+			.ok_or(RefError::OutOfBounds)
+	};
+
+	while code_pos < code.len() {
+		let op = code[code_pos];
+
+		match op {
+			99 => break,
+			1 | 2 => {
+				let arg1pos = addr(code, code_pos+1)?;
+				let arg2pos = addr(code, code_pos+2)?;
+				let respos = addr(code, code_pos+3)?;
+
+				code[respos] = match op {
+					1 => code[arg1pos].checked_add(code[arg2pos]),
+					_ => code[arg1pos].checked_mul(code[arg2pos]),
+				}.ok_or(RefError::Overflow)?;
+				code_pos += 4;
+			},
+			_ => return Err(RefError::InvalidOpcode),
+		}
+	}
+
+	Ok(())
+}
+
+// A straight-line program of adds and multiplies over in-bounds addresses.
+// Some writes still land on later instructions' opcodes or addresses.
+fn random_day2_program(rng: &mut Rng) -> Vec<i64> {
+	let instructions = 1 + rng.below(12) as usize;
+	let data = instructions * 4 + 1;
+	let len = data + 1 + rng.below(8) as usize;
+
+	let mut code: Vec<i64> = (0..len).map(|_| rng.below(20) as i64).collect();
+	for i in 0..instructions {
+		code[i*4] = *rng.pick(&[1, 2]);
+		code[i*4 + 1] = rng.below(len as u64) as i64;
+		code[i*4 + 2] = rng.below(len as u64) as i64;
+		// mostly write past the code, so most programs stay well-formed
+		code[i*4 + 3] = match rng.below(5) {
+			0 => rng.below(len as u64),
+			_ => data as u64 + rng.below((len - data) as u64),
+		} as i64;
+	}
+	code[instructions*4] = 99;
+
+	code
+}
+
+fn run_machine(code: &[i64], kind: MemoryKind, fast: bool)
+-> (Result<State, Error>, Vec<i64>, Vec<i64>) {
+	let mut machine = Machine::from_memory(Memory::with_kind(code.to_vec(), kind));
+	machine.set_instruction_budget(Some(INSTRUCTION_BUDGET));
+	machine.memory_mut().set_limit(MEMORY_LIMIT);
+	machine.push_input(0);
+	machine.push_input(-1);
+	machine.push_input(i64::MAX);
+
+	let mut outputs = Vec::<i64>::new();
+	let result = loop {
+		let result = match fast {
+			false => machine.run(),
+			true => machine.run_fast(),
+		};
+		match result {
+			Ok(State::Produced(value)) => outputs.push(value),
+			result => break result,
+		}
+	};
+
+	(result, outputs, machine.into_program())
+}
+
+// `Some(description)` if the two disagree, `None` if they agree or the
+// program left the subset the reference understands.
+fn check_day2(code: &[i64]) -> Option<String> {
+	let mut expected = code.to_vec();
+	let reference = reference_exec(&mut expected);
+	let (result, _, memory) = run_machine(code, MemoryKind::Dense, false);
+
+	match (reference, result) {
+		(Ok(()), Ok(State::Halted)) if memory == expected => None,
+		(Err(RefError::Overflow), Err(Error{kind: ErrorKind::Overflow, ..})) => None,
+		(Err(RefError::InvalidOpcode), _) | (Err(RefError::OutOfBounds), _) => None,
+		(reference, result) => Some(format!(
+			"reference gave {:?} {:?}, machine gave {:?} {:?}",
+			reference, expected, result, memory
+		)),
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+fn random_word(rng: &mut Rng) -> i64 {
+	match rng.below(8) {
+		0 => *rng.pick(&[0, -1, 1, i64::MIN, i64::MAX, 99]),
+		1 => rng.next() as i64,
+		2 | 3 => rng.below(64) as i64 - 16,
+		_ => {
+			// a plausible instruction: an opcode with a few mode digits
+			let op = *rng.pick(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 99]);
+			let modes = (0..3).fold(0, |acc, _| acc * 10 + rng.below(4) as i64);
+			modes * 100 + op
+		},
+	}
+}
+
+// `Some(description)` if any path panics or they disagree.
+fn check_robust(code: &[i64]) -> Option<String> {
+	let run = |name, kind, fast| {
+		panic::catch_unwind(AssertUnwindSafe(|| run_machine(code, kind, fast)))
+			.map_err(|_| format!("{} panicked", name))
+	};
+	let slow = run("run", MemoryKind::Dense, false);
+	let fast = run("run_fast", MemoryKind::Dense, true);
+	let sparse = run("sparse run", MemoryKind::Sparse, false);
+
+	match (slow, fast, sparse) {
+		(Ok(slow), Ok(fast), Ok(sparse)) if slow == fast && slow == sparse => None,
+		(Ok(slow), Ok(fast), Ok(sparse)) => Some(format!(
+			"run gave {:?}, run_fast gave {:?}, sparse run gave {:?}",
+			slow.0, fast.0, sparse.0
+		)),
+		(Err(failure), _, _) | (_, Err(failure), _) | (_, _, Err(failure)) => Some(failure),
+	}
+}
+
+//------------------------------------------------------------------
+
+fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let iterations = args.first()
+		.map_or(10_000, |n| n.parse::<u64>().expect("invalid iteration count"));
+	let seed = args.get(1)
+		.map(|n| n.parse::<u64>().expect("invalid seed"))
+		.unwrap_or_else(|| {
+			std::time::SystemTime::now()
+				.duration_since(std::time::UNIX_EPOCH)
+				.map_or(1, |d| d.as_nanos() as u64)
+		});
+	println!("seed {}", seed);
+
+	let mut rng = Rng::new(seed);
+	let mut failures = 0u64;
+	for i in 0..iterations {
+		let day2 = random_day2_program(&mut rng);
+		let len = 1 + rng.below(64) as usize;
+		let words: Vec<i64> = (0..len).map(|_| random_word(&mut rng)).collect();
+
+		for (target, code, failure) in [
+			("day2", &day2, check_day2(&day2)),
+			("robust", &words, check_robust(&words)),
+		].iter() {
+			if let Some(failure) = failure {
+				failures += 1;
+				println!("[{} #{}] {}", target, i, failure);
+				println!("{}", to_code_string(code));
+			}
+		}
+	}
+
+	println!("{} iterations, {} failures", iterations, failures);
+	if failures > 0 {
+		std::process::exit(1);
+	}
+}
@@ -0,0 +1,219 @@
+// Chains of machines running copies of one program, each seeded with a phase
+// setting and feeding its outputs to the next machine's input. In a feedback
+// loop the last machine's outputs go back to the first, until it halts.
+
+use std::fmt;
+use std::vec::Vec;
+
+use super::error::Error;
+use super::machine::{Machine, State};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wiring {
+	Series,
+	Feedback,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmpError {
+	// the machine at this index in the chain failed
+	Machine(usize, Error),
+	// every machine is waiting for input and none is left to give
+	Stalled,
+	// the last machine halted without ever producing a signal
+	NoOutput,
+}
+
+impl fmt::Display for AmpError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Machine(index, error) => write!(f, "amplifier {}: {}", index, error),
+			Self::Stalled => write!(f, "amplifiers stalled waiting for input"),
+			Self::NoOutput => write!(f, "last amplifier produced no output"),
+		}
+	}
+}
+
+impl std::error::Error for AmpError {}
+
+
+#[derive(Debug, Clone)]
+pub struct Amplifiers {
+	// every amplifier starts as a copy of this
+	machine: Machine,
+	wiring: Wiring,
+}
+
+impl Amplifiers {
+	pub fn new(program: Vec<i64>, wiring: Wiring) -> Self {
+		Self::from_machine(Machine::new(program), wiring)
+	}
+
+	// Amplifiers that keep the memory backend, limits and other settings of
+	// `machine`, which should not have started running.
+	pub fn from_machine(machine: Machine, wiring: Wiring) -> Self {
+		Self{machine, wiring}
+	}
+
+	// Applies to each machine separately.
+	pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+		self.machine.set_instruction_budget(budget);
+	}
+
+	// Runs one machine per phase setting, with `signal` as the first
+	// machine's second input, and returns the last signal the final machine
+	// produced.
+	pub fn run(&self, phases: &[i64], signal: i64) -> Result<i64, AmpError> {
+		let mut machines: Vec<Machine> = phases.iter().map(|&phase| {
+			let mut machine = self.machine.clone();
+			machine.push_input(phase);
+			machine
+		}).collect();
+		let mut halted = vec![false; machines.len()];
+
+		let mut signals = vec![signal];
+		let mut result = None;
+		loop {
+			let mut progressed = false;
+
+			for (i, machine) in machines.iter_mut().enumerate() {
+				for value in signals.drain(..) {
+					machine.push_input(value);
+				}
+				if halted[i] {
+					continue;
+				}
+
+				loop {
+					match machine.run().map_err(|error| AmpError::Machine(i, error))? {
+						State::Produced(value) => signals.push(value),
+						State::Halted | State::Exited(_) => {
+							halted[i] = true;
+							break;
+						},
+						_ => break,
+					};
+				}
+				progressed |= !signals.is_empty();
+			}
+
+			if let Some(&value) = signals.last() {
+				result = Some(value);
+			}
+			if self.wiring == Wiring::Series || halted.last().cloned().unwrap_or(true) {
+				break;
+			}
+			if !progressed {
+				return Err(AmpError::Stalled);
+			}
+		}
+
+		result.ok_or(AmpError::NoOutput)
+	}
+
+	// The ordering of `settings` giving the highest final signal, starting
+	// from a signal of 0.
+	pub fn best_phases(&self, settings: &[i64]) -> Result<(Vec<i64>, i64), AmpError> {
+		let mut best: Option<(Vec<i64>, i64)> = None;
+		for phases in permutations(settings) {
+			let signal = self.run(&phases, 0)?;
+			if best.as_ref().is_none_or(|&(_, best_signal)| signal > best_signal) {
+				best = Some((phases, signal));
+			}
+		}
+
+		best.ok_or(AmpError::NoOutput)
+	}
+}
+
+
+// All orderings of `items`, in lexicographic order of their indices.
+fn permutations(items: &[i64]) -> Vec<Vec<i64>> {
+	if items.is_empty() {
+		return vec![Vec::new()];
+	}
+
+	let mut result = Vec::<Vec<i64>>::new();
+	for i in 0..items.len() {
+		let mut rest = items.to_vec();
+		let first = rest.remove(i);
+		for mut tail in permutations(&rest) {
+			tail.insert(0, first);
+			result.push(tail);
+		}
+	}
+	result
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{AmpError, Amplifiers, Wiring};
+	use super::super::error::ErrorKind;
+	use super::super::machine::{Arithmetic, Machine};
+
+	#[test]
+	fn day7_series_example() {
+		let program = vec![3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0];
+		let amplifiers = Amplifiers::new(program, Wiring::Series);
+		assert_eq!(amplifiers.run(&[4, 3, 2, 1, 0], 0), Ok(43210));
+		assert_eq!(amplifiers.best_phases(&[0, 1, 2, 3, 4]), Ok((vec![4, 3, 2, 1, 0], 43210)));
+	}
+
+	#[test]
+	fn day7_feedback_example() {
+		let program = vec![
+			3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26,
+			27, 4, 27, 1001, 28, -1, 28, 1005, 28, 6, 99, 0, 0, 5,
+		];
+		let amplifiers = Amplifiers::new(program, Wiring::Feedback);
+		assert_eq!(amplifiers.run(&[9, 8, 7, 6, 5], 0), Ok(139629729));
+		assert_eq!(
+			amplifiers.best_phases(&[5, 6, 7, 8, 9]),
+			Ok((vec![9, 8, 7, 6, 5], 139629729))
+		);
+	}
+
+	#[test]
+	fn failures() {
+		// keeps asking for input without ever answering
+		let amplifiers = Amplifiers::new(vec![3, 0, 3, 0, 3, 0, 99], Wiring::Feedback);
+		assert_eq!(amplifiers.run(&[0, 1], 0), Err(AmpError::Stalled));
+
+		let amplifiers = Amplifiers::new(vec![3, 0, 3, 0, 99], Wiring::Series);
+		assert_eq!(amplifiers.run(&[0, 1], 0), Err(AmpError::NoOutput));
+
+		let amplifiers = Amplifiers::new(vec![3, 0, 4, 0, 42], Wiring::Series);
+		match amplifiers.run(&[0, 1], 0) {
+			Err(AmpError::Machine(0, error)) => assert_eq!(error.kind, ErrorKind::InvalidOpcode(42)),
+			result => panic!("unexpected {:?}", result),
+		}
+
+		let mut amplifiers = Amplifiers::new(vec![3, 5, 1105, 1, 2, 0], Wiring::Series);
+		amplifiers.set_instruction_budget(Some(50));
+		match amplifiers.run(&[0], 0) {
+			Err(AmpError::Machine(0, error)) => {
+				assert_eq!(error.kind, ErrorKind::BudgetExhausted(50));
+			},
+			result => panic!("unexpected {:?}", result),
+		}
+	}
+
+	#[test]
+	fn amplifiers_keep_the_machine_settings() {
+		// doubles its input, which overflows past the first amplifier
+		let program = vec![3, 11, 3, 12, 2, 11, 12, 13, 4, 13, 99, 0, 0, 0];
+		let mut machine = Machine::new(program.clone());
+		machine.set_arithmetic(Arithmetic::Wrapping);
+
+		let amplifiers = Amplifiers::from_machine(machine, Wiring::Series);
+		assert_eq!(amplifiers.run(&[2, 2], i64::MAX), Ok(-4));
+		match Amplifiers::new(program, Wiring::Series).run(&[2, 2], i64::MAX) {
+			Err(AmpError::Machine(0, error)) => assert_eq!(error.kind, ErrorKind::Overflow),
+			result => panic!("unexpected {:?}", result),
+		}
+	}
+}
@@ -0,0 +1,159 @@
+// Text I/O for programs that speak ASCII: outputs below 128 are characters,
+// anything larger is printed as a number on its own line, and input is read
+// a line at a time and fed as character codes ending in a newline (10).
+
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+use super::error::Error;
+use super::io::{InputSource, OutputSink};
+use super::machine::{Machine, State};
+
+
+#[derive(Debug)]
+pub struct Ascii<R, W> {
+	reader: R,
+	writer: W,
+	pending: VecDeque<i64>,
+	at_line_start: bool,
+	// everything shown and typed, as it appeared
+	transcript: Option<String>,
+}
+
+impl<R: BufRead, W: Write> Ascii<R, W> {
+	pub fn new(reader: R, writer: W) -> Self {
+		Self{
+			reader,
+			writer,
+			pending: VecDeque::new(),
+			at_line_start: true,
+			transcript: None,
+		}
+	}
+
+	pub fn record_transcript(&mut self) {
+		self.transcript.get_or_insert_with(String::new);
+	}
+
+	pub fn transcript(&self) -> Option<&str> {
+		self.transcript.as_deref()
+	}
+
+	pub fn into_inner(self) -> (R, W) {
+		(self.reader, self.writer)
+	}
+
+	// Queues a line as if it had been typed; the newline is added.
+	pub fn push_line(&mut self, line: &str) {
+		self.pending.extend(line.bytes().map(i64::from));
+		self.pending.push_back(10);
+		if let Some(transcript) = self.transcript.as_mut() {
+			transcript.push_str(line);
+			transcript.push('\n');
+		}
+	}
+
+	pub fn run(&mut self, machine: &mut Machine) -> Result<State, Error> {
+		machine.drive(self, Machine::run)
+	}
+
+	fn write_str(&mut self, text: &str) {
+		self.writer.write_all(text.as_bytes()).expect("failed to write output");
+		if text.ends_with('\n') {
+			self.writer.flush().expect("failed to write output");
+		}
+		if let Some(transcript) = self.transcript.as_mut() {
+			transcript.push_str(text);
+		}
+		self.at_line_start = text.ends_with('\n');
+	}
+}
+
+impl<R: BufRead, W: Write> InputSource for Ascii<R, W> {
+	fn next_input(&mut self) -> Option<i64> {
+		if self.pending.is_empty() {
+			self.writer.flush().expect("failed to write output");
+
+			let mut line = String::new();
+			match self.reader.read_line(&mut line) {
+				Ok(0) | Err(_) => return None,
+				Ok(_) => {},
+			};
+			let line = line.trim_end_matches(['\n', '\r']);
+			self.push_line(line);
+		}
+
+		self.pending.pop_front()
+	}
+}
+
+impl<R: BufRead, W: Write> OutputSink for Ascii<R, W> {
+	fn put_output(&mut self, value: i64) {
+		match value {
+			0..=127 => self.write_str(&(value as u8 as char).to_string()),
+			_ => {
+				let prefix = if self.at_line_start {""} else {"\n"};
+				self.write_str(&format!("{}{}\n", prefix, value));
+			},
+		};
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::io::BufRead;
+
+	use super::Ascii;
+	use super::super::asm::assemble;
+	use super::super::machine::{Machine, State};
+
+	// Greets, echoes one line, then prints numbers around a character.
+	fn echo() -> Machine {
+		Machine::new(assemble("
+				out #72
+				out #105
+				out #10
+		loop:	in [c]
+				out [c]
+				eq [c], #10, [t]
+				jz [t], #loop
+				out #1000
+				out #33
+				out #2000
+				hlt
+		c:		data 0
+		t:		data 0
+		").unwrap())
+	}
+
+	#[test]
+	fn characters_numbers_and_lines() {
+		let mut ascii = Ascii::new("abc\r\nmore\n".as_bytes(), Vec::new());
+		ascii.record_transcript();
+		assert_eq!(ascii.run(&mut echo()), Ok(State::Halted));
+		assert_eq!(ascii.transcript(), Some("Hi\nabc\nabc\n1000\n!\n2000\n"));
+
+		let (mut reader, writer) = ascii.into_inner();
+		assert_eq!(String::from_utf8(writer).unwrap(), "Hi\nabc\n1000\n!\n2000\n");
+		// only the line the program asked for was read
+		let mut rest = String::new();
+		reader.read_line(&mut rest).unwrap();
+		assert_eq!(rest, "more\n");
+	}
+
+	#[test]
+	fn queued_lines_and_end_of_input() {
+		let mut ascii = Ascii::new("".as_bytes(), Vec::new());
+		ascii.push_line("xy");
+		assert_eq!(ascii.run(&mut echo()), Ok(State::Halted));
+		assert_eq!(ascii.transcript(), None);
+		assert_eq!(ascii.into_inner().1, b"Hi\nxy\n1000\n!\n2000\n");
+
+		let mut ascii = Ascii::new("".as_bytes(), Vec::new());
+		let mut machine = echo();
+		assert_eq!(ascii.run(&mut machine), Ok(State::NeedsInput));
+		assert_eq!(ascii.into_inner().1, b"Hi\n");
+	}
+}
@@ -0,0 +1,330 @@
+// A small assembly language for Intcode, mirroring the disassembler's syntax:
+//
+//     ; comments run to the end of the line
+//     loop:   in [x]                  ; position operand
+//             add [x], #-1, [rb+2]    ; immediate and relative operands
+//             jnz [x], #loop          ; labels resolve to their address
+//             hlt
+//     x:      data 0, loop+1          ; raw words
+//
+// Mnemonics are those of `OpInstruction::mnemonic`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::vec::Vec;
+
+use super::opcode::OpInstruction;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+	UnknownMnemonic(String),
+	OperandCount{expected: u8, found: usize},
+	BadOperand(String),
+	ImmediateWrite,
+	BadLabel(String),
+	DuplicateLabel(String),
+	UnknownLabel(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+	pub line: usize,
+	pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: ", self.line)?;
+		match &self.kind {
+			AsmErrorKind::UnknownMnemonic(m) => write!(
+				f, "unknown mnemonic '{}'", m
+			),
+			AsmErrorKind::OperandCount{expected, found} => write!(
+				f, "expected {} operands, found {}", expected, found
+			),
+			AsmErrorKind::BadOperand(s) => write!(f, "invalid operand '{}'", s),
+			AsmErrorKind::ImmediateWrite => write!(
+				f, "written operand cannot be immediate"
+			),
+			AsmErrorKind::BadLabel(s) => write!(f, "invalid label '{}'", s),
+			AsmErrorKind::DuplicateLabel(s) => write!(
+				f, "label '{}' defined more than once", s
+			),
+			AsmErrorKind::UnknownLabel(s) => write!(
+				f, "label '{}' is never defined", s
+			),
+		}
+	}
+}
+
+impl std::error::Error for AsmError {}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Expr {
+	Number(i64),
+	Label(String, i64),
+}
+
+impl Expr {
+	fn parse(s: &str) -> Option<Expr> {
+		let s = s.trim();
+		if let Ok(n) = s.parse::<i64>() {
+			return Some(Self::Number(n));
+		}
+
+		let (name, offset) = match s.find(['+', '-']) {
+			Some(i) => (
+				s[..i].trim(),
+				s[i..].replace(" ", "").trim_start_matches('+').parse::<i64>().ok()?
+			),
+			None => (s, 0),
+		};
+		if !is_label(name) {
+			return None;
+		}
+
+		Some(Self::Label(name.to_string(), offset))
+	}
+
+	fn resolve(&self, labels: &HashMap<String, usize>, line: usize)
+	-> Result<i64, AsmError> {
+		match self {
+			Self::Number(n) => Ok(*n),
+			Self::Label(name, offset) => {
+				let addr = labels.get(name).ok_or(AsmError{
+					line,
+					kind: AsmErrorKind::UnknownLabel(name.clone()),
+				})?;
+				(*addr as i64).checked_add(*offset).ok_or(AsmError{
+					line,
+					kind: AsmErrorKind::BadOperand(format!("{}{:+}", name, offset)),
+				})
+			},
+		}
+	}
+}
+
+fn is_label(s: &str) -> bool {
+	let mut chars = s.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => {},
+		_ => return false,
+	};
+
+	s != "rb" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// returns the parameter mode and the expression for its value
+fn parse_operand(s: &str) -> Option<(u32, Expr)> {
+	let s = s.trim();
+
+	if let Some(rest) = s.strip_prefix('#') {
+		return Some((1, Expr::parse(rest)?));
+	}
+
+	let inner = s.strip_prefix('[')?.strip_suffix(']')?.trim();
+	match inner.strip_prefix("rb") {
+		Some("") => Some((2, Expr::Number(0))),
+		Some(offset) if offset.trim_start().starts_with(['+', '-']) => {
+			let offset = offset.replace(" ", "");
+			Some((2, Expr::Number(
+				offset.trim_start_matches('+').parse::<i64>().ok()?
+			)))
+		},
+		_ => Some((0, Expr::parse(inner)?)),
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Statement {
+	Op(OpInstruction, Vec<(u32, Expr)>),
+	Data(Vec<Expr>),
+}
+
+impl Statement {
+	fn len(&self) -> usize {
+		match self {
+			Self::Op(_, operands) => 1 + operands.len(),
+			Self::Data(values) => values.len(),
+		}
+	}
+}
+
+fn parse_statement(text: &str, line: usize) -> Result<Statement, AsmError> {
+	let error = |kind| AsmError{line, kind};
+
+	let (mnemonic, rest) = match text.find(char::is_whitespace) {
+		Some(i) => (&text[..i], text[i..].trim()),
+		None => (text, ""),
+	};
+	let args: Vec<&str> = if rest.is_empty() {
+		vec![]
+	} else {
+		rest.split(',').map(|a| a.trim()).collect()
+	};
+	let mnemonic = mnemonic.to_lowercase();
+
+	if mnemonic == "data" {
+		let values = args.iter()
+			.map(|a| Expr::parse(a).ok_or(
+				error(AsmErrorKind::BadOperand(a.to_string()))
+			))
+			.collect::<Result<Vec<_>, _>>()?;
+		return Ok(Statement::Data(values));
+	}
+
+	let op = OpInstruction::from_mnemonic(&mnemonic).ok_or(
+		error(AsmErrorKind::UnknownMnemonic(mnemonic.clone()))
+	)?;
+	if args.len() != usize::from(op.param_count()) {
+		return Err(error(AsmErrorKind::OperandCount{
+			expected: op.param_count(), found: args.len()
+		}));
+	}
+
+	let mut operands = Vec::<(u32, Expr)>::new();
+	for (i, arg) in args.iter().enumerate() {
+		let (mode, expr) = parse_operand(arg).ok_or(
+			error(AsmErrorKind::BadOperand(arg.to_string()))
+		)?;
+		if mode == 1 && op.write_param() == Some(i as u8) {
+			return Err(error(AsmErrorKind::ImmediateWrite));
+		}
+		operands.push((mode, expr));
+	}
+
+	Ok(Statement::Op(op, operands))
+}
+
+
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+	let mut labels = HashMap::<String, usize>::new();
+	let mut statements = Vec::<(usize, Statement)>::new();
+	let mut addr = 0usize;
+
+	for (i, raw_line) in source.lines().enumerate() {
+		let line = i+1;
+		let mut text = raw_line.split(';').next().unwrap().trim();
+
+		while let Some(colon) = text.find(':') {
+			let name = text[..colon].trim();
+			if !is_label(name) {
+				return Err(AsmError{
+					line, kind: AsmErrorKind::BadLabel(name.to_string())
+				});
+			}
+			if labels.insert(name.to_string(), addr).is_some() {
+				return Err(AsmError{
+					line,
+					kind: AsmErrorKind::DuplicateLabel(name.to_string()),
+				});
+			}
+			text = text[colon+1..].trim();
+		}
+		if text.is_empty() {
+			continue;
+		}
+
+		let statement = parse_statement(text, line)?;
+		addr += statement.len();
+		statements.push((line, statement));
+	}
+
+	let mut program = Vec::<i64>::with_capacity(addr);
+	for (line, statement) in statements.iter() {
+		match statement {
+			Statement::Op(op, operands) => {
+				let mut word = i64::from(op.opcode());
+				let mut scale = 100i64;
+				for (mode, _) in operands.iter() {
+					word += i64::from(*mode) * scale;
+					scale *= 10;
+				}
+				program.push(word);
+
+				for (_, expr) in operands.iter() {
+					program.push(expr.resolve(&labels, *line)?);
+				}
+			},
+			Statement::Data(values) => {
+				for expr in values.iter() {
+					program.push(expr.resolve(&labels, *line)?);
+				}
+			},
+		}
+	}
+
+	Ok(program)
+}
+
+// the comma-separated form read by `parse_program`
+pub fn to_code_string(program: &[i64]) -> String {
+	program.iter()
+		.map(|w| w.to_string())
+		.collect::<Vec<_>>()
+		.join(",")
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{assemble, to_code_string, AsmError, AsmErrorKind};
+
+	fn error(source: &str) -> Option<AsmError> {
+		assemble(source).err()
+	}
+
+	#[test]
+	fn encodes_modes_and_labels() {
+		let source = "
+			in [x]              ; read a value
+	loop:	add [x], #-1, [rb+2]
+			jnz [x], #loop
+			out [rb-1]
+			hlt
+	x:		data 0, loop+1
+		";
+		assert_eq!(
+			assemble(source),
+			Ok(vec![3, 12, 21001, 12, -1, 2, 1005, 12, 2, 204, -1, 99, 0, 3])
+		);
+	}
+
+	#[test]
+	fn round_trips_through_code_strings() {
+		let program = assemble("mul [4], #3, [4]\nhlt\ndata 33").ok();
+		assert_eq!(program.as_ref().map(|p| to_code_string(p)), Some("1002,4,3,4,99,33".to_string()));
+	}
+
+	#[test]
+	fn reports_errors_with_lines() {
+		assert_eq!(error("hlt\nfoo [1]"), Some(AsmError{
+			line: 2, kind: AsmErrorKind::UnknownMnemonic("foo".to_string()),
+		}));
+		assert_eq!(error("add [1], [2]"), Some(AsmError{
+			line: 1, kind: AsmErrorKind::OperandCount{expected: 3, found: 2},
+		}));
+		assert_eq!(error("in #5"), Some(AsmError{
+			line: 1, kind: AsmErrorKind::ImmediateWrite,
+		}));
+		assert_eq!(error("a: hlt\na: hlt"), Some(AsmError{
+			line: 2, kind: AsmErrorKind::DuplicateLabel("a".to_string()),
+		}));
+		assert_eq!(error("jz #0, #nowhere"), Some(AsmError{
+			line: 1, kind: AsmErrorKind::UnknownLabel("nowhere".to_string()),
+		}));
+	}
+
+	#[test]
+	fn rejects_overflowing_offsets() {
+		assert_eq!(error("hlt\nx: data x+9223372036854775807"), Some(AsmError{
+			line: 2, kind: AsmErrorKind::BadOperand("x+9223372036854775807".to_string()),
+		}));
+	}
+}
@@ -0,0 +1,339 @@
+// Arbitrary-precision signed integers, just enough for Intcode: addition,
+// multiplication, comparison, and conversion to and from text and i64.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Mul, Neg};
+use std::str::FromStr;
+use std::vec::Vec;
+
+
+// Sign and magnitude, the magnitude in base 2^32 limbs, least significant
+// first, with no trailing zero limbs; zero is never negative.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+	negative: bool,
+	magnitude: Vec<u32>,
+}
+
+fn trim(mut magnitude: Vec<u32>) -> Vec<u32> {
+	while magnitude.last() == Some(&0) {
+		magnitude.pop();
+	}
+	magnitude
+}
+
+fn cmp_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+	a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut sum = Vec::<u32>::with_capacity(a.len().max(b.len()) + 1);
+	let mut carry = 0u64;
+	for i in 0..a.len().max(b.len()) {
+		let total = u64::from(*a.get(i).unwrap_or(&0)) + u64::from(*b.get(i).unwrap_or(&0)) + carry;
+		sum.push(total as u32);
+		carry = total >> 32;
+	}
+	sum.push(carry as u32);
+	trim(sum)
+}
+
+// `a - b`, where `a >= b`
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut difference = Vec::<u32>::with_capacity(a.len());
+	let mut borrow = 0i64;
+	for (i, &digit) in a.iter().enumerate() {
+		let mut total = i64::from(digit) - i64::from(*b.get(i).unwrap_or(&0)) - borrow;
+		borrow = (total < 0) as i64;
+		total += borrow << 32;
+		difference.push(total as u32);
+	}
+	trim(difference)
+}
+
+fn mul_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+	let mut product = vec![0u32; a.len() + b.len()];
+	for (i, &x) in a.iter().enumerate() {
+		let mut carry = 0u64;
+		for (j, &y) in b.iter().enumerate() {
+			let total = u64::from(x) * u64::from(y) + u64::from(product[i+j]) + carry;
+			product[i+j] = total as u32;
+			carry = total >> 32;
+		}
+		product[i + b.len()] = carry as u32;
+	}
+	trim(product)
+}
+
+// Divides in place by a small divisor, returning the remainder.
+fn div_rem_small(magnitude: &mut Vec<u32>, divisor: u32) -> u32 {
+	let mut remainder = 0u64;
+	for limb in magnitude.iter_mut().rev() {
+		let current = (remainder << 32) | u64::from(*limb);
+		*limb = (current / u64::from(divisor)) as u32;
+		remainder = current % u64::from(divisor);
+	}
+	*magnitude = trim(std::mem::take(magnitude));
+	remainder as u32
+}
+
+
+impl BigInt {
+	fn from_parts(negative: bool, magnitude: Vec<u32>) -> Self {
+		let magnitude = trim(magnitude);
+		Self{negative: negative && !magnitude.is_empty(), magnitude}
+	}
+
+	pub fn zero() -> Self {
+		Self::default()
+	}
+
+	pub fn is_zero(&self) -> bool {
+		self.magnitude.is_empty()
+	}
+
+	pub fn is_negative(&self) -> bool {
+		self.negative
+	}
+
+	pub fn to_i64(&self) -> Option<i64> {
+		if self.magnitude.len() > 2 {
+			return None;
+		}
+		let value = self.magnitude.iter().rev()
+			.fold(0u64, |acc, &limb| acc << 32 | u64::from(limb));
+
+		match self.negative {
+			false => i64::try_from(value).ok(),
+			true if value == 1 << 63 => Some(i64::MIN),
+			true => i64::try_from(value).ok().map(|value| -value),
+		}
+	}
+}
+
+impl From<i64> for BigInt {
+	fn from(value: i64) -> Self {
+		let magnitude = value.unsigned_abs();
+		Self::from_parts(value < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+	}
+}
+
+impl Ord for BigInt {
+	fn cmp(&self, other: &BigInt) -> Ordering {
+		match (self.negative, other.negative) {
+			(false, true) => Ordering::Greater,
+			(true, false) => Ordering::Less,
+			(false, false) => cmp_magnitude(&self.magnitude, &other.magnitude),
+			(true, true) => cmp_magnitude(&other.magnitude, &self.magnitude),
+		}
+	}
+}
+
+impl PartialOrd for BigInt {
+	fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl<'a> Add<&'a BigInt> for &'a BigInt {
+	type Output = BigInt;
+
+	fn add(self, other: &BigInt) -> BigInt {
+		if self.negative == other.negative {
+			return BigInt::from_parts(
+				self.negative, add_magnitude(&self.magnitude, &other.magnitude)
+			);
+		}
+
+		match cmp_magnitude(&self.magnitude, &other.magnitude) {
+			Ordering::Less => BigInt::from_parts(
+				other.negative, sub_magnitude(&other.magnitude, &self.magnitude)
+			),
+			_ => BigInt::from_parts(
+				self.negative, sub_magnitude(&self.magnitude, &other.magnitude)
+			),
+		}
+	}
+}
+
+impl<'a> Mul<&'a BigInt> for &'a BigInt {
+	type Output = BigInt;
+
+	fn mul(self, other: &BigInt) -> BigInt {
+		BigInt::from_parts(
+			self.negative != other.negative,
+			mul_magnitude(&self.magnitude, &other.magnitude),
+		)
+	}
+}
+
+impl Neg for BigInt {
+	type Output = BigInt;
+
+	fn neg(self) -> BigInt {
+		let negative = !self.negative;
+		BigInt::from_parts(negative, self.magnitude)
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// digits converted per limb operation when going to or from text
+const CHUNK_DIGITS: usize = 9;
+const CHUNK: u32 = 1_000_000_000;
+
+impl fmt::Display for BigInt {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mut magnitude = self.magnitude.clone();
+		let mut chunks = Vec::<u32>::new();
+		while !magnitude.is_empty() {
+			chunks.push(div_rem_small(&mut magnitude, CHUNK));
+		}
+
+		let mut digits = chunks.pop().unwrap_or(0).to_string();
+		for chunk in chunks.iter().rev() {
+			digits.push_str(&format!("{:09}", chunk));
+		}
+		f.pad_integral(!self.negative, "", &digits)
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseBigIntError;
+
+impl fmt::Display for ParseBigIntError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "invalid integer")
+	}
+}
+
+impl std::error::Error for ParseBigIntError {}
+
+impl FromStr for BigInt {
+	type Err = ParseBigIntError;
+
+	fn from_str(text: &str) -> Result<BigInt, ParseBigIntError> {
+		let (negative, digits) = match text.as_bytes().first() {
+			Some(b'-') => (true, &text[1..]),
+			Some(b'+') => (false, &text[1..]),
+			_ => (false, text),
+		};
+		if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+			return Err(ParseBigIntError);
+		}
+
+		let mut magnitude = Vec::<u32>::new();
+		let first = match digits.len() % CHUNK_DIGITS {
+			0 => CHUNK_DIGITS,
+			n => n,
+		};
+		let mut start = 0;
+		let mut end = first;
+		while start < digits.len() {
+			let chunk = digits[start..end].parse::<u32>().map_err(|_| ParseBigIntError)?;
+			let scale = 10u32.pow((end - start) as u32);
+			magnitude = add_magnitude(&mul_magnitude(&magnitude, &[scale]), &[chunk]);
+			start = end;
+			end += CHUNK_DIGITS;
+		}
+
+		Ok(BigInt::from_parts(negative, magnitude))
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::BigInt;
+
+	fn big(value: i128) -> BigInt {
+		value.to_string().parse().unwrap()
+	}
+
+	// a fixed spread of values around the interesting boundaries
+	fn samples() -> Vec<i128> {
+		let mut values = vec![
+			0, 1, -1, 2, -2, 999_999_999, 1_000_000_000,
+			u32::MAX as i128, u32::MAX as i128 + 1, -(u32::MAX as i128) - 1,
+			i64::MAX as i128, i64::MIN as i128, i64::MAX as i128 + 1,
+		];
+		let mut state = 0x2545_f491_4f6c_dd1du64;
+		for _ in 0..40 {
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			values.push(state as i64 as i128 >> (state % 64));
+		}
+		values
+	}
+
+	#[test]
+	fn arithmetic_matches_i128() {
+		let values = samples();
+		for &a in values.iter() {
+			for &b in values.iter() {
+				assert_eq!(&big(a) + &big(b), big(a + b), "{} + {}", a, b);
+				assert_eq!(big(a).cmp(&big(b)), a.cmp(&b), "{} <=> {}", a, b);
+				if let Some(product) = a.checked_mul(b) {
+					assert_eq!(&big(a) * &big(b), big(product), "{} * {}", a, b);
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn converts_to_and_from_text() {
+		for &value in samples().iter() {
+			assert_eq!(big(value).to_string(), value.to_string());
+		}
+		let huge = "-123456789012345678901234567890123456789";
+		assert_eq!(huge.parse::<BigInt>().unwrap().to_string(), huge);
+		assert_eq!("+007".parse::<BigInt>(), Ok(BigInt::from(7)));
+		assert_eq!("-0".parse::<BigInt>().map(|zero| zero.is_negative()), Ok(false));
+		assert_eq!(format!("{:>5}", BigInt::from(-42)), "  -42");
+
+		for &bad in ["", "-", "1.5", "12a", " 1"].iter() {
+			assert!(bad.parse::<BigInt>().is_err(), "{:?}", bad);
+		}
+	}
+
+	#[test]
+	fn converts_to_i64_only_when_it_fits() {
+		assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+		assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+		assert_eq!(big(i64::MAX as i128 + 1).to_i64(), None);
+		assert_eq!(big(i64::MIN as i128 - 1).to_i64(), None);
+		assert_eq!(big(1 << 100).to_i64(), None);
+	}
+
+	#[test]
+	fn runs_programs_past_i64() {
+		use super::super::{ErrorKind, Machine, Memory, Snapshot, State};
+
+		let a = i64::MAX as i128 + 12345;
+		let b = i64::MIN as i128 - 7;
+		let program = vec![big(1002), big(7), big(b), big(7), big(4), big(7), big(99), big(a)];
+
+		for &sparse in [false, true].iter() {
+			let memory = match sparse {
+				true => Memory::sparse(program.clone()),
+				false => Memory::new(program.clone()),
+			};
+			let mut machine = Machine::from_memory(memory);
+			assert_eq!(machine.run(), Ok(State::Produced(big(a * b))));
+
+			let mut text = Vec::new();
+			machine.snapshot().write_to(&mut text).unwrap();
+			let mut restored = Machine::new(vec![big(99)]);
+			restored.restore(Snapshot::read_from(&text[..]).unwrap());
+			assert_eq!(restored.run(), Ok(State::Halted));
+		}
+
+		let mut machine = Machine::new(vec![big(1105), big(1), big(a)]);
+		assert_eq!(machine.run().map_err(|e| e.kind), Err(ErrorKind::BadJumpTarget(big(a))));
+	}
+}
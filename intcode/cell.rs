@@ -0,0 +1,76 @@
+// What a machine's memory holds: i64 words, or big integers for programs
+// whose values outgrow them. Everything else about the machine (decoding,
+// the instruction set, memory backends, tooling) is shared.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+use super::bigint::BigInt;
+use super::machine::Arithmetic;
+use super::registry::Registry;
+
+
+pub trait Cell:
+	Clone + Default + Ord + fmt::Debug + fmt::Display + FromStr + From<i64>
+	+ Send + Sync + 'static
+{
+	// The value as an i64, for use as an opcode, address or offset.
+	fn to_i64(&self) -> Option<i64>;
+
+	fn is_zero(&self) -> bool {
+		*self == Self::default()
+	}
+
+	// `None` on overflow; `arithmetic` only matters to cells that can.
+	fn sum(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+	fn product(&self, other: &Self, arithmetic: Arithmetic) -> Option<Self>;
+
+	// The core instruction set, shared by every machine of this cell type.
+	fn shared_core() -> Arc<Registry<Self>>;
+}
+
+impl Cell for i64 {
+	#[inline]
+	fn to_i64(&self) -> Option<i64> {
+		Some(*self)
+	}
+
+	#[inline]
+	fn sum(&self, other: &i64, arithmetic: Arithmetic) -> Option<i64> {
+		arithmetic.add(*self, *other)
+	}
+
+	#[inline]
+	fn product(&self, other: &i64, arithmetic: Arithmetic) -> Option<i64> {
+		arithmetic.mul(*self, *other)
+	}
+
+	fn shared_core() -> Arc<Registry<i64>> {
+		static CORE: OnceLock<Arc<Registry<i64>>> = OnceLock::new();
+		CORE.get_or_init(|| Arc::new(Registry::core())).clone()
+	}
+}
+
+impl Cell for BigInt {
+	fn to_i64(&self) -> Option<i64> {
+		BigInt::to_i64(self)
+	}
+
+	fn is_zero(&self) -> bool {
+		BigInt::is_zero(self)
+	}
+
+	fn sum(&self, other: &BigInt, _: Arithmetic) -> Option<BigInt> {
+		Some(self + other)
+	}
+
+	fn product(&self, other: &BigInt, _: Arithmetic) -> Option<BigInt> {
+		Some(self * other)
+	}
+
+	fn shared_core() -> Arc<Registry<BigInt>> {
+		static CORE: OnceLock<Arc<Registry<BigInt>>> = OnceLock::new();
+		CORE.get_or_init(|| Arc::new(Registry::core())).clone()
+	}
+}
@@ -0,0 +1,299 @@
+// Static control-flow graph of an Intcode image, built without running it.
+//
+// Exploration starts at address 0 and follows fallthroughs and jumps with
+// immediate targets. Jumps through position or relative operands can go
+// anywhere, so they get an edge to an "unknown" node instead. The image is
+// assumed not to modify its own code.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Write as FmtWrite;
+use std::vec::Vec;
+
+use super::disasm::{decode, Instruction, Operand};
+use super::opcode::OpInstruction;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+	Fallthrough,
+	Taken,
+	NotTaken,
+	Indirect,
+}
+
+impl EdgeKind {
+	fn label(&self) -> &'static str {
+		match self {
+			Self::Fallthrough => "",
+			Self::Taken => "taken",
+			Self::NotTaken => "not taken",
+			Self::Indirect => "indirect",
+		}
+	}
+}
+
+// `target` is `None` when it can't be known statically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+	pub kind: EdgeKind,
+	pub target: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+	Halt,
+	// the next word doesn't decode, or the image ends
+	Invalid(usize),
+	Jump,
+	// control continues into the next block
+	Leader,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+	pub start: usize,
+	pub instructions: Vec<(usize, Instruction)>,
+	pub terminator: Terminator,
+	pub edges: Vec<Edge>,
+}
+
+
+// Outgoing edges of an instruction, or `None` if it falls through.
+fn jump_edges(pos: usize, instruction: &Instruction) -> Option<Vec<Edge>> {
+	let trigger = match instruction.op {
+		OpInstruction::Jump(trigger) => trigger,
+		_ => return None,
+	};
+	let fallthrough = pos + instruction.size();
+
+	let target = match instruction.operands[1] {
+		Operand::Immediate(value) => Edge{
+			kind: EdgeKind::Taken,
+			target: usize::try_from(value).ok(),
+		},
+		_ => Edge{kind: EdgeKind::Indirect, target: None},
+	};
+	let not_taken = Edge{kind: EdgeKind::NotTaken, target: Some(fallthrough)};
+
+	Some(match instruction.operands[0] {
+		Operand::Immediate(value) if (value != 0) == trigger => vec![target],
+		Operand::Immediate(_) => vec![not_taken],
+		_ => vec![target, not_taken],
+	})
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cfg {
+	pub blocks: BTreeMap<usize, Block>,
+}
+
+impl Cfg {
+	pub fn build(program: &[i64]) -> Cfg {
+		// first find every reachable block leader...
+		let mut leaders = BTreeSet::<usize>::new();
+		let mut visited = BTreeSet::<usize>::new();
+		let mut worklist = vec![0usize];
+		leaders.insert(0);
+
+		while let Some(start) = worklist.pop() {
+			let mut pos = start;
+			while visited.insert(pos) {
+				let instruction = match decode(program, pos) {
+					Some(instruction) => instruction,
+					None => break,
+				};
+				if instruction.op == OpInstruction::Terminate {
+					break;
+				}
+
+				if let Some(edges) = jump_edges(pos, &instruction) {
+					for target in edges.iter().filter_map(|edge| edge.target) {
+						leaders.insert(target);
+						worklist.push(target);
+					}
+					break;
+				}
+				pos += instruction.size();
+			}
+		}
+
+		// ...then cut the code into blocks at those leaders
+		let mut blocks = BTreeMap::<usize, Block>::new();
+		for &start in leaders.iter() {
+			let mut block = Block{
+				start,
+				instructions: Vec::new(),
+				terminator: Terminator::Leader,
+				edges: Vec::new(),
+			};
+
+			let mut pos = start;
+			loop {
+				if pos != start && leaders.contains(&pos) {
+					block.edges.push(Edge{
+						kind: EdgeKind::Fallthrough, target: Some(pos)
+					});
+					break;
+				}
+				let instruction = match decode(program, pos) {
+					Some(instruction) => instruction,
+					None => {
+						block.terminator = Terminator::Invalid(pos);
+						break;
+					}
+				};
+				let next = pos + instruction.size();
+				let edges = jump_edges(pos, &instruction);
+				let halts = instruction.op == OpInstruction::Terminate;
+				block.instructions.push((pos, instruction));
+
+				if halts {
+					block.terminator = Terminator::Halt;
+					break;
+				}
+				if let Some(edges) = edges {
+					block.terminator = Terminator::Jump;
+					block.edges = edges;
+					break;
+				}
+				pos = next;
+			}
+
+			blocks.insert(start, block);
+		}
+
+		Cfg{blocks}
+	}
+
+	pub fn to_dot(&self) -> String {
+		let mut dot = String::new();
+		let mut has_unknown = false;
+
+		writeln!(dot, "digraph intcode {{").unwrap();
+		writeln!(dot, "\tnode [shape=box, fontname=\"monospace\"];").unwrap();
+
+		for block in self.blocks.values() {
+			let mut label = String::new();
+			for (pos, instruction) in block.instructions.iter() {
+				write!(label, "{}: {}\\l", pos, instruction).unwrap();
+			}
+			if let Terminator::Invalid(pos) = block.terminator {
+				write!(label, "{}: DATA\\l", pos).unwrap();
+			}
+			writeln!(dot, "\tb{} [label=\"{}\"];", block.start, label).unwrap();
+		}
+
+		for block in self.blocks.values() {
+			for edge in block.edges.iter() {
+				let style = match edge.kind {
+					EdgeKind::Indirect => ", style=dashed",
+					_ => "",
+				};
+				let target = match edge.target {
+					Some(target) if self.blocks.contains_key(&target) => {
+						format!("b{}", target)
+					},
+					_ => {
+						has_unknown = true;
+						"unknown".to_string()
+					},
+				};
+
+				writeln!(
+					dot, "\tb{} -> {} [label=\"{}\"{}];",
+					block.start, target, edge.kind.label(), style
+				).unwrap();
+			}
+		}
+
+		if has_unknown {
+			writeln!(dot, "\tunknown [shape=ellipse, style=dashed];").unwrap();
+		}
+		writeln!(dot, "}}").unwrap();
+
+		dot
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{Cfg, Edge, EdgeKind, Terminator};
+	use super::super::asm::assemble;
+
+	fn edge(kind: EdgeKind, target: Option<usize>) -> Edge {
+		Edge{kind, target}
+	}
+
+	fn fixture() -> Cfg {
+		let program = assemble("
+				in [x]
+		loop:	add [x], #-1, [x]
+				jnz [x], #loop
+				jz [x], [y]
+				jnz #1, #end
+				out [x]
+		end:	out [x]
+		x:		data 0
+		y:		data 0
+		").unwrap();
+		Cfg::build(&program)
+	}
+
+	#[test]
+	fn splits_blocks_at_leaders() {
+		let cfg = fixture();
+		assert_eq!(cfg.blocks.keys().cloned().collect::<Vec<_>>(), [0, 2, 9, 12, 17]);
+
+		let block = &cfg.blocks[&0];
+		assert_eq!(block.instructions.len(), 1);
+		assert_eq!(block.terminator, Terminator::Leader);
+		assert_eq!(block.edges, [edge(EdgeKind::Fallthrough, Some(2))]);
+
+		let block = &cfg.blocks[&17];
+		assert_eq!(block.instructions.iter().map(|(pos, _)| *pos).collect::<Vec<_>>(), [17]);
+		assert_eq!(block.terminator, Terminator::Invalid(19));
+		assert!(block.edges.is_empty());
+	}
+
+	#[test]
+	fn edges_of_jumps() {
+		let cfg = fixture();
+		assert_eq!(cfg.blocks[&2].terminator, Terminator::Jump);
+		assert_eq!(cfg.blocks[&2].edges, [
+			edge(EdgeKind::Taken, Some(2)), edge(EdgeKind::NotTaken, Some(9)),
+		]);
+		assert_eq!(cfg.blocks[&9].edges, [
+			edge(EdgeKind::Indirect, None), edge(EdgeKind::NotTaken, Some(12)),
+		]);
+		// an immediate condition decides the jump
+		assert_eq!(cfg.blocks[&12].edges, [edge(EdgeKind::Taken, Some(17))]);
+		assert_eq!(Cfg::build(&[1106, 1, 7, 99]).blocks[&0].edges, [
+			edge(EdgeKind::NotTaken, Some(3)),
+		]);
+	}
+
+	#[test]
+	fn dot_output() {
+		let dot = fixture().to_dot();
+		for line in [
+			"\tb2 [label=\"2: add [19], #-1, [19]\\l6: jnz [19], #2\\l\"];",
+			"\tb17 [label=\"17: out [19]\\l19: DATA\\l\"];",
+			"\tb0 -> b2 [label=\"\"];",
+			"\tb2 -> b2 [label=\"taken\"];",
+			"\tb2 -> b9 [label=\"not taken\"];",
+			"\tb9 -> unknown [label=\"indirect\", style=dashed];",
+			"\tunknown [shape=ellipse, style=dashed];",
+		].iter() {
+			assert!(dot.lines().any(|l| l == *line), "missing {:?} in\n{}", line, dot);
+		}
+		assert!(dot.starts_with("digraph intcode {\n"));
+		assert!(dot.ends_with("}\n"));
+		assert!(!dot.contains("15:"));
+
+		assert!(!Cfg::build(&[99]).to_dot().contains("unknown"));
+	}
+}
@@ -0,0 +1,512 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::{BufRead, Write as IoWrite};
+use std::vec::Vec;
+
+use super::cell::Cell;
+use super::digits::Digits;
+use super::error::Error;
+use super::machine::{Effect, Machine, State, Write};
+use super::registry::OpSpec;
+use super::reverse::UndoLog;
+use super::snapshot::Snapshot;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event<C = i64> {
+	Stepped,
+	Breakpoint(usize),
+	// the address and the mnemonic of the instruction there
+	OpBreakpoint(usize, &'static str),
+	Watch(Write<C>),
+	NeedsInput,
+	Output(C),
+	Halted,
+	Exited(C),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Debugger<C = i64> {
+	machine: Machine<C>,
+	breakpoints: HashSet<usize>,
+	// opcodes in the machine's instruction set
+	op_breakpoints: HashSet<u32>,
+	watches: HashSet<usize>,
+	history: UndoLog<C>,
+	// the breakpoint last reported, so that resuming from it works
+	stopped_at: Option<usize>,
+}
+
+impl<C: Cell> Debugger<C> {
+	const HISTORY_CAPACITY: usize = 1_000_000;
+
+	pub fn new(machine: Machine<C>) -> Self {
+		Self{
+			machine,
+			breakpoints: HashSet::new(),
+			op_breakpoints: HashSet::new(),
+			watches: HashSet::new(),
+			history: UndoLog::with_capacity(Self::HISTORY_CAPACITY),
+			stopped_at: None,
+		}
+	}
+
+	pub fn machine(&self) -> &Machine<C> {
+		&self.machine
+	}
+
+	pub fn machine_mut(&mut self) -> &mut Machine<C> {
+		&mut self.machine
+	}
+
+	pub fn into_machine(self) -> Machine<C> {
+		self.machine
+	}
+
+	pub fn add_breakpoint(&mut self, addr: usize) {
+		self.breakpoints.insert(addr);
+	}
+
+	pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+		self.breakpoints.remove(&addr)
+	}
+
+	pub fn add_op_breakpoint(&mut self, opcode: u32) {
+		self.op_breakpoints.insert(opcode);
+	}
+
+	pub fn remove_op_breakpoint(&mut self, opcode: u32) -> bool {
+		self.op_breakpoints.remove(&opcode)
+	}
+
+	pub fn add_watch(&mut self, addr: usize) {
+		self.watches.insert(addr);
+	}
+
+	pub fn remove_watch(&mut self, addr: usize) -> bool {
+		self.watches.remove(&addr)
+	}
+
+	// The instruction at the instruction pointer, from the machine's own
+	// instruction set, and the word holding its opcode and modes.
+	pub fn current_op(&self) -> Option<(u32, &OpSpec<C>)> {
+		let word = self.machine.memory().peek(self.machine.position()).to_i64()
+			.and_then(|word| u32::try_from(word).ok())?;
+		let opcode: u32 = Digits::from(word).subdigits(..2).into();
+		self.machine.registry().get(opcode).map(|spec| (word, spec))
+	}
+
+	// Looks up an instruction in the machine's instruction set.
+	fn opcode_of(&self, mnemonic: &str) -> Result<u32, String> {
+		self.machine.registry().by_mnemonic(mnemonic)
+			.map(|spec| spec.opcode)
+			.ok_or(format!("no instruction '{}'", mnemonic))
+	}
+
+	pub fn step(&mut self) -> Result<Event<C>, Error<C>> {
+		let state = self.machine.step()?;
+		if let Some(effect) = self.machine.last_effect() {
+			// still at the breakpoint if nothing ran, e.g. waiting for input
+			self.stopped_at = None;
+			// a halt is executed again on every step, so it can't be undone
+			if effect.counted {
+				self.history.record(effect);
+			}
+			if let Some(write) = &effect.write {
+				if self.watches.contains(&write.addr) {
+					return Ok(Event::Watch(write.clone()));
+				}
+			}
+		}
+
+		Ok(match state {
+			State::Running => Event::Stepped,
+			State::NeedsInput => Event::NeedsInput,
+			State::Produced(value) => Event::Output(value),
+			State::Halted => Event::Halted,
+			State::Exited(value) => Event::Exited(value),
+		})
+	}
+
+	// Runs until something worth reporting happens. The breakpoint just
+	// reported does not trigger again, so that resuming from it works.
+	pub fn resume(&mut self) -> Result<Event<C>, Error<C>> {
+		loop {
+			let pos = self.machine.position();
+			if self.stopped_at != Some(pos) {
+				if self.breakpoints.contains(&pos) {
+					self.stopped_at = Some(pos);
+					return Ok(Event::Breakpoint(pos));
+				}
+				let op = self.current_op().map(|(_, spec)| (spec.opcode, spec.mnemonic));
+				if let Some((opcode, mnemonic)) = op {
+					if self.op_breakpoints.contains(&opcode) {
+						self.stopped_at = Some(pos);
+						return Ok(Event::OpBreakpoint(pos, mnemonic));
+					}
+				}
+			}
+
+			match self.step()? {
+				Event::Stepped => continue,
+				event => return Ok(event),
+			}
+		}
+	}
+
+	// Undoes the last instruction; `None` once the history runs out.
+	pub fn step_back(&mut self) -> Option<Effect<C>> {
+		self.stopped_at = None;
+		self.history.step_back(&mut self.machine)
+	}
+
+	// Runs backwards until a breakpoint is reached or a write to a watched
+	// address is undone. Stops at the start of the recorded history.
+	pub fn reverse(&mut self) -> Option<Event<C>> {
+		loop {
+			let effect = self.step_back()?;
+
+			if let Some(write) = effect.write {
+				if self.watches.contains(&write.addr) {
+					return Some(Event::Watch(write));
+				}
+			}
+			let pos = self.machine.position();
+			if self.breakpoints.contains(&pos) {
+				self.stopped_at = Some(pos);
+				return Some(Event::Breakpoint(pos));
+			}
+		}
+	}
+
+	pub fn back_to_write(&mut self, addr: usize) -> Option<Write<C>> {
+		self.stopped_at = None;
+		self.history.back_to_write(&mut self.machine, addr)
+	}
+
+	//-------------------------------------------------------------------------
+
+	pub fn repl<R: BufRead, W: IoWrite>(&mut self, input: R, mut output: W)
+	-> std::io::Result<()> {
+		writeln!(output, "intcode debugger; 'help' lists the commands")?;
+		self.print_location(&mut output)?;
+
+		for line in input.lines() {
+			let line = line?;
+			let words: Vec<&str> = line.split_whitespace().collect();
+			if words.is_empty() {
+				continue;
+			}
+
+			match self.command(&words, &mut output) {
+				Ok(true) => continue,
+				Ok(false) => break,
+				Err(message) => writeln!(output, "error: {}", message)?,
+			}
+		}
+
+		Ok(())
+	}
+
+	fn command<W: IoWrite>(&mut self, words: &[&str], output: &mut W)
+	-> Result<bool, String> {
+		let arg = |i: usize| -> Result<C, String> {
+			words.get(i)
+				.ok_or(format!("'{}' needs more arguments", words[0]))?
+				.parse::<C>()
+				.map_err(|_| format!("invalid number '{}'", words[i]))
+		};
+		let addr = |i: usize| -> Result<usize, String> {
+			let value = arg(i)?;
+			match value.to_i64().map(usize::try_from) {
+				Some(Ok(addr)) => Ok(addr),
+				_ => Err(format!("invalid address {}", value)),
+			}
+		};
+		let io_err = |e: std::io::Error| e.to_string();
+
+		match words[0] {
+			"h" | "help" => writeln!(output, "{}", HELP).map_err(io_err)?,
+			"q" | "quit" => return Ok(false),
+			"s" | "step" => {
+				let count = if words.len() > 1 {addr(1)?} else {1};
+				for _ in 0..count {
+					let event = self.step().map_err(|e| e.to_string())?;
+					self.print_event(&event, output).map_err(io_err)?;
+					if event != Event::Stepped {
+						break;
+					}
+				}
+				self.print_location(output).map_err(io_err)?;
+			},
+			"c" | "continue" => loop {
+				let event = self.resume().map_err(|e| e.to_string())?;
+				self.print_event(&event, output).map_err(io_err)?;
+				if let Event::Output(_) = event {
+					continue;
+				}
+				self.print_location(output).map_err(io_err)?;
+				break;
+			},
+			"bs" | "back" => {
+				let count = if words.len() > 1 {addr(1)?} else {1};
+				for _ in 0..count {
+					if self.step_back().is_none() {
+						writeln!(output, "at the start of the recorded history")
+							.map_err(io_err)?;
+						break;
+					}
+				}
+				self.print_location(output).map_err(io_err)?;
+			},
+			"rc" | "reverse" => {
+				match self.reverse() {
+					Some(event) => self.print_event(&event, output),
+					None => writeln!(output, "at the start of the recorded history"),
+				}.map_err(io_err)?;
+				self.print_location(output).map_err(io_err)?;
+			},
+			"lw" | "last-write" => {
+				let a = addr(1)?;
+				match self.back_to_write(a) {
+					Some(write) => writeln!(
+						output, "undid write: [{}] {} -> {}",
+						write.addr, write.old, write.new
+					),
+					None => writeln!(output, "no recorded write to {}", a),
+				}.map_err(io_err)?;
+				self.print_location(output).map_err(io_err)?;
+			},
+			"b" | "break" => self.add_breakpoint(addr(1)?),
+			"bo" | "break-op" => {
+				let opcode = self.opcode_of(words.get(1).ok_or("expected a mnemonic")?)?;
+				self.add_op_breakpoint(opcode);
+			},
+			"w" | "watch" => self.add_watch(addr(1)?),
+			"d" | "delete" => {
+				let a = addr(1)?;
+				let found = self.remove_breakpoint(a) | self.remove_watch(a);
+				if !found {
+					return Err(format!("no breakpoint or watch at {}", a));
+				}
+			},
+			"do" | "delete-op" => {
+				let opcode = self.opcode_of(words.get(1).ok_or("expected a mnemonic")?)?;
+				if !self.remove_op_breakpoint(opcode) {
+					return Err(format!("no breakpoint on '{}'", words[1]));
+				}
+			},
+			"x" | "examine" => {
+				let start = addr(1)?;
+				let count = if words.len() > 2 {addr(2)?} else {1};
+				for a in start..start+count {
+					let value = self.machine.memory().read(a)
+						.map_err(|e| e.to_string())?;
+					writeln!(output, "[{}] = {}", a, value).map_err(io_err)?;
+				}
+			},
+			"set" => {
+				let a = addr(1)?;
+				let value = arg(2)?;
+				*self.machine.memory_mut().get_mut(a)
+					.map_err(|e| e.to_string())? = value;
+				// the recorded history no longer leads to this state
+				self.history.clear();
+			},
+			"save" => {
+				let path = words.get(1).ok_or("'save' needs a file path")?;
+				self.machine.snapshot().save(path).map_err(io_err)?;
+			},
+			"load" => {
+				let path = words.get(1).ok_or("'load' needs a file path")?;
+				self.machine.restore(Snapshot::load(path).map_err(io_err)?);
+				self.history.clear();
+				self.stopped_at = None;
+				self.print_location(output).map_err(io_err)?;
+			},
+			"in" | "input" => self.machine.push_input(arg(1)?),
+			"p" | "print" => self.print_location(output).map_err(io_err)?,
+			"i" | "info" => {
+				let mut breakpoints: Vec<_> = self.breakpoints.iter().collect();
+				breakpoints.sort();
+				let mut watches: Vec<_> = self.watches.iter().collect();
+				watches.sort();
+				let mut ops: Vec<_> = self.op_breakpoints.iter()
+					.filter_map(|&opcode| self.machine.registry().get(opcode))
+					.map(|spec| spec.mnemonic)
+					.collect();
+				ops.sort();
+
+				writeln!(
+					output,
+					"ip {}  rb {}  pending input {:?}\n\
+					breakpoints {:?}\nop breakpoints {:?}\nwatches {:?}",
+					self.machine.position(), self.machine.relative_base(),
+					self.machine.pending_inputs(), breakpoints, ops, watches,
+				).map_err(io_err)?;
+			},
+			cmd => return Err(format!("unknown command '{}'", cmd)),
+		}
+
+		Ok(true)
+	}
+
+	// Operands are shown as the disassembler shows them, but in full for
+	// cells too large for it. Anything the machine would refuse to execute
+	// is shown as DATA.
+	fn print_location<W: IoWrite>(&self, output: &mut W) -> std::io::Result<()> {
+		let pos = self.machine.position();
+		if let Some((word, spec)) = self.current_op() {
+			let modes = Digits::from(word);
+			let operands = self.machine.memory().window(pos+1, usize::from(spec.arity));
+			let operands = operands.iter().enumerate()
+				.map(|(i, value)| {
+					let mode: u32 = modes.subdigits(2+i as u8..3+i as u8).into();
+					match mode {
+						1 if spec.write_param == Some(i as u8) => None,
+						0 => Some(format!("[{}]", value)),
+						1 => Some(format!("#{}", value)),
+						2 if *value < C::default() => Some(format!("[rb{}]", value)),
+						2 => Some(format!("[rb+{}]", value)),
+						_ => None,
+					}
+				})
+				.collect::<Option<Vec<_>>>()
+				.filter(|operands| operands.len() == usize::from(spec.arity));
+			if let Some(operands) = operands {
+				let separator = if operands.is_empty() {""} else {" "};
+				return writeln!(
+					output, "{:>6}: {}{}{}", pos, spec.mnemonic, separator, operands.join(", ")
+				);
+			}
+		}
+		let word = self.machine.memory().read(pos).unwrap_or_default();
+		writeln!(output, "{:>6}: DATA {}", pos, word)
+	}
+
+	fn print_event<W: IoWrite>(&self, event: &Event<C>, output: &mut W)
+	-> std::io::Result<()> {
+		match event {
+			Event::Stepped => Ok(()),
+			Event::Breakpoint(pos) => writeln!(output, "breakpoint at {}", pos),
+			Event::OpBreakpoint(pos, mnemonic) => writeln!(
+				output, "breakpoint on '{}' at {}", mnemonic, pos
+			),
+			Event::Watch(write) => writeln!(
+				output, "watch: [{}] {} -> {}", write.addr, write.old, write.new
+			),
+			Event::NeedsInput => writeln!(
+				output, "waiting for input; supply it with 'in VALUE'"
+			),
+			Event::Output(value) => writeln!(output, "output: {}", value),
+			Event::Halted => writeln!(output, "program halted"),
+			Event::Exited(value) => writeln!(output, "program exited with {}", value),
+		}
+	}
+}
+
+
+const HELP: &str = "\
+s, step [N]          execute N instructions (default 1)
+c, continue          run until a breakpoint, watch, input request or halt
+bs, back [N]         undo the last N instructions (default 1)
+rc, reverse          run backwards to a breakpoint or watched write
+lw, last-write ADDR  run back to just before the last write to ADDR
+b, break ADDR        break when the instruction pointer reaches ADDR
+bo, break-op MNEM    break before any instruction with mnemonic MNEM
+w, watch ADDR        stop after any write to ADDR
+d, delete ADDR       remove the breakpoint and watch at ADDR
+do, delete-op MNEM   remove the breakpoint on MNEM
+x, examine ADDR [N]  print N memory cells starting at ADDR
+set ADDR VALUE       overwrite a memory cell (clears the history)
+save FILE            write a snapshot of the machine to FILE
+load FILE            restore the machine from a snapshot in FILE
+in, input VALUE      queue a value for the program's next input
+p, print             show the current instruction
+i, info              show registers, breakpoints and watches
+q, quit              leave the debugger";
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use super::{Debugger, Event};
+	use super::super::machine::{Exec, Machine, Write};
+	use super::super::registry::{Flow, OpSpec, Registry};
+
+	#[test]
+	fn stops_at_a_breakpoint_reached_by_an_output() {
+		let mut debugger = Debugger::new(Machine::new(vec![104, 7, 1101, 1, 1, 9, 99, 0, 0, 0]));
+		debugger.add_breakpoint(2);
+
+		assert_eq!(debugger.resume(), Ok(Event::Output(7)));
+		assert_eq!(debugger.resume(), Ok(Event::Breakpoint(2)));
+		assert_eq!(debugger.resume(), Ok(Event::Halted));
+	}
+
+	#[test]
+	fn stops_at_a_breakpoint_reached_by_a_watched_write() {
+		let mut debugger = Debugger::new(Machine::new(vec![1101, 1, 1, 7, 104, 5, 99, 0]));
+		debugger.add_breakpoint(4);
+		debugger.add_watch(7);
+
+		assert_eq!(debugger.resume(), Ok(Event::Watch(Write{addr: 7, old: 0, new: 2})));
+		assert_eq!(debugger.resume(), Ok(Event::Breakpoint(4)));
+		assert_eq!(debugger.resume(), Ok(Event::Output(5)));
+		assert_eq!(debugger.resume(), Ok(Event::Halted));
+	}
+
+	#[test]
+	fn waiting_for_input_stays_at_the_breakpoint() {
+		let mut debugger = Debugger::new(Machine::new(vec![3, 5, 4, 5, 99, 0]));
+		debugger.add_breakpoint(0);
+
+		assert_eq!(debugger.resume(), Ok(Event::Breakpoint(0)));
+		assert_eq!(debugger.resume(), Ok(Event::NeedsInput));
+		debugger.machine_mut().push_input(5);
+		assert_eq!(debugger.resume(), Ok(Event::Output(5)));
+		assert_eq!(debugger.resume(), Ok(Event::Halted));
+	}
+
+	#[test]
+	fn repl_continues_to_the_breakpoint() {
+		let mut debugger = Debugger::new(Machine::new(vec![104, 7, 1101, 1, 1, 9, 99, 0, 0, 0]));
+		let mut output = Vec::<u8>::new();
+		debugger.repl("b 2\nc\nc\n".as_bytes(), &mut output).unwrap();
+
+		let output = String::from_utf8(output).unwrap();
+		let events: Vec<&str> = output.lines()
+			.filter(|line| !line.starts_with(' ') && !line.starts_with("intcode"))
+			.collect();
+		assert_eq!(events, ["output: 7", "breakpoint at 2", "program halted"]);
+	}
+
+	#[test]
+	fn repl_knows_custom_instructions() {
+		let mut registry = Registry::core();
+		registry.register(OpSpec::new(50, "exit", 1, None, |exec: &mut Exec| {
+			Ok(Flow::Exit(exec.read(0)?))
+		}));
+		let program = vec![1101, 1, 1, 5, 1150, 0];
+		let mut debugger = Debugger::new(Machine::with_registry(program, Arc::new(registry)));
+		let mut output = Vec::<u8>::new();
+		debugger.repl("bo exit\nc\ni\nc\nbo nop\n".as_bytes(), &mut output).unwrap();
+
+		let output = String::from_utf8(output).unwrap();
+		assert_eq!(output.lines().skip(1).collect::<Vec<_>>(), [
+			"     0: add #1, #1, [5]",
+			"breakpoint on 'exit' at 4",
+			"     4: exit #2",
+			"ip 4  rb 0  pending input []",
+			"breakpoints []",
+			"op breakpoints [\"exit\"]",
+			"watches []",
+			"program exited with 2",
+			"     4: exit #2",
+			"error: no instruction 'nop'",
+		]);
+	}
+}
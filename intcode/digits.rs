@@ -0,0 +1,44 @@
+use std::cmp::min;
+use std::ops::{RangeBounds, Bound::*};
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct Digits(u32);
+
+impl Digits {
+	const NO_OF_DIGITS: u8 = 9;
+
+	pub fn subdigits<R: RangeBounds<u8>>(&self, index: R) -> Self {
+		let lbound = match index.start_bound() {
+			Unbounded => 0,
+			Included(&n) => n,
+			Excluded(&n) => n+1
+		};
+		let ubound = match index.end_bound() {
+			Unbounded => Self::NO_OF_DIGITS,
+			Included(&n) => min(Self::NO_OF_DIGITS, n+1),
+			Excluded(&n) => min(Self::NO_OF_DIGITS, n),
+		};
+
+		Self::from(
+			if lbound >= ubound {
+				0_u32
+			} else {
+				(self.0 / 10_u32.pow(lbound.into()))
+				% 10_u32.pow((ubound-lbound).into())
+			}
+		)
+	}
+}
+
+impl From<u32> for Digits {
+	fn from(value: u32) -> Self {
+		Self(value)
+	}
+}
+
+impl From<Digits> for u32 {
+	fn from(digits: Digits) -> u32 {
+		digits.0
+	}
+}
@@ -0,0 +1,221 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::vec::Vec;
+
+use super::digits::Digits;
+use super::opcode::OpInstruction;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+	Position(i64),
+	Immediate(i64),
+	Relative(i64),
+}
+
+impl Operand {
+	fn from_mode(mode: u32, value: i64) -> Option<Operand> {
+		match mode {
+			0 => Some(Self::Position(value)),
+			1 => Some(Self::Immediate(value)),
+			2 => Some(Self::Relative(value)),
+			_ => None,
+		}
+	}
+
+	pub fn mode(&self) -> u32 {
+		match self {
+			Self::Position(_) => 0,
+			Self::Immediate(_) => 1,
+			Self::Relative(_) => 2,
+		}
+	}
+
+	pub fn value(&self) -> i64 {
+		match self {
+			Self::Position(v) | Self::Immediate(v) | Self::Relative(v) => *v,
+		}
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Position(addr) => write!(f, "[{}]", addr),
+			Self::Immediate(value) => write!(f, "#{}", value),
+			Self::Relative(offset) if *offset < 0 => write!(
+				f, "[rb-{}]", offset.unsigned_abs()
+			),
+			Self::Relative(offset) => write!(f, "[rb+{}]", offset),
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+	pub op: OpInstruction,
+	pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+	// in words, including the opcode
+	pub fn size(&self) -> usize {
+		1 + self.operands.len()
+	}
+}
+
+impl fmt::Display for Instruction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.op.mnemonic())?;
+		for (i, operand) in self.operands.iter().enumerate() {
+			write!(f, "{}{}", if i == 0 {" "} else {", "}, operand)?;
+		}
+		Ok(())
+	}
+}
+
+
+// `None` for anything the interpreter would refuse to execute.
+pub fn decode(program: &[i64], pos: usize) -> Option<Instruction> {
+	let word = u32::try_from(*program.get(pos)?).ok()?;
+	let op = OpInstruction::from_opcode(word).ok()?;
+	let modes = Digits::from(word);
+
+	let mut operands = Vec::<Operand>::new();
+	for offset in 0..op.param_count() {
+		let mode: u32 = modes.subdigits(2+offset..3+offset).into();
+		let value = *program.get(pos+1+usize::from(offset))?;
+
+		let operand = Operand::from_mode(mode, value)?;
+		if op.write_param() == Some(offset) && mode == 1 {
+			return None;
+		}
+		operands.push(operand);
+	}
+
+	Some(Instruction{op, operands})
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Listing {
+	Code(Instruction),
+	Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+	pub addr: usize,
+	pub words: Vec<i64>,
+	pub listing: Listing,
+}
+
+impl fmt::Display for Line {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let raw = self.words.iter()
+			.map(|w| w.to_string())
+			.collect::<Vec<_>>()
+			.join(",");
+
+		match &self.listing {
+			Listing::Code(instruction) => write!(
+				f, "{:>6}: {:<28} {}", self.addr, raw, instruction
+			),
+			Listing::Data => write!(
+				f, "{:>6}: {:<28} DATA {}", self.addr, raw, raw
+			),
+		}
+	}
+}
+
+
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+	let mut lines = Vec::<Line>::new();
+	let mut pos = 0usize;
+
+	while pos < program.len() {
+		let line = match decode(program, pos) {
+			Some(instruction) => Line{
+				addr: pos,
+				words: program[pos..pos+instruction.size()].to_vec(),
+				listing: Listing::Code(instruction),
+			},
+			None => Line{
+				addr: pos,
+				words: vec![program[pos]],
+				listing: Listing::Data,
+			},
+		};
+
+		pos += line.words.len();
+		lines.push(line);
+	}
+
+	lines
+}
+
+pub fn print_listing(program: &[i64]) {
+	for line in disassemble(program) {
+		println!("{}", line);
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{decode, disassemble, Instruction, Listing, Operand};
+	use super::super::opcode::OpInstruction;
+
+	fn squeeze(text: &str) -> String {
+		text.split_whitespace().collect::<Vec<_>>().join(" ")
+	}
+
+	#[test]
+	fn decodes_modes() {
+		let program = [21001, 12, -1, -2];
+		assert_eq!(decode(&program, 0), Some(Instruction{
+			op: OpInstruction::Add,
+			operands: vec![Operand::Position(12), Operand::Immediate(-1), Operand::Relative(-2)],
+		}));
+		assert_eq!(decode(&program, 0).unwrap().to_string(), "add [12], #-1, [rb-2]");
+		assert_eq!(decode(&[204, 3], 0).unwrap().to_string(), "out [rb+3]");
+		assert_eq!(decode(&[99], 0).unwrap().size(), 1);
+	}
+
+	#[test]
+	fn refuses_what_would_not_execute() {
+		// immediate-mode writes, for each instruction that writes
+		assert_eq!(decode(&[11101, 1, 2, 3], 0), None);
+		assert_eq!(decode(&[103, 5], 0), None);
+		assert_eq!(decode(&[11107, 1, 2, 3], 0), None);
+		// unknown opcodes and modes, negative words, missing operands
+		assert_eq!(decode(&[42], 0), None);
+		assert_eq!(decode(&[301, 1, 2, 3], 0), None);
+		assert_eq!(decode(&[-1], 0), None);
+		assert_eq!(decode(&[1, 2], 0), None);
+		assert_eq!(decode(&[99], 1), None);
+	}
+
+	#[test]
+	fn lists_code_and_data() {
+		let program = [3, 12, 21001, 12, -1, -2, 11101, 42, 99, -5, 1, 2];
+		let lines = disassemble(&program);
+
+		let listed = lines.iter().map(|line| squeeze(&line.to_string())).collect::<Vec<_>>();
+		assert_eq!(listed, [
+			"0: 3,12 in [12]",
+			"2: 21001,12,-1,-2 add [12], #-1, [rb-2]",
+			"6: 11101 DATA 11101",
+			"7: 42 DATA 42",
+			"8: 99 hlt",
+			"9: -5 DATA -5",
+			"10: 1 DATA 1",
+			"11: 2 DATA 2",
+		]);
+		assert_eq!(lines[2].listing, Listing::Data);
+		assert_eq!(lines.iter().map(|line| line.words.len()).sum::<usize>(), program.len());
+	}
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+
+// Values from the program are reported as cells of the machine's type, so
+// that big-integer machines report them exactly too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind<C = i64> {
+	InvalidOpcode(C),
+	InvalidParamMode{param: u8, mode: u32},
+	ImmediateWrite{param: u8},
+	OutOfBounds(usize),
+	NegativeAddress(C),
+	// too large to be an address at all, let alone one below the limit
+	AddressTooLarge(C),
+	Overflow,
+	BadJumpTarget(C),
+	BudgetExhausted(u64),
+	InfiniteLoop{period: u64},
+}
+
+impl<C: fmt::Display> fmt::Display for ErrorKind<C> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::InvalidOpcode(n) => write!(f, "invalid opcode {}", n),
+			Self::InvalidParamMode{param, mode} => write!(
+				f, "invalid mode {} for parameter {}", mode, param
+			),
+			Self::ImmediateWrite{param} => write!(
+				f, "parameter {} is written to but uses immediate mode", param
+			),
+			Self::OutOfBounds(addr) => write!(
+				f, "address {} is beyond the memory limit", addr
+			),
+			Self::NegativeAddress(addr) => write!(
+				f, "negative address {}", addr
+			),
+			Self::AddressTooLarge(addr) => write!(
+				f, "{} is too large to be an address", addr
+			),
+			Self::Overflow => write!(f, "arithmetic overflow"),
+			Self::BadJumpTarget(target) => write!(
+				f, "invalid jump target {}", target
+			),
+			Self::BudgetExhausted(budget) => write!(
+				f, "instruction budget of {} exhausted", budget
+			),
+			Self::InfiniteLoop{period} => write!(
+				f, "infinite loop: machine state repeats every {} instructions",
+				period
+			),
+		}
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Error<C = i64> {
+	pub pos: usize,
+	pub word: C,
+	pub kind: ErrorKind<C>,
+}
+
+impl<C: fmt::Display> fmt::Display for Error<C> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f, "{} (instruction {} at position {})",
+			self.kind, self.word, self.pos
+		)
+	}
+}
+
+impl<C: fmt::Debug + fmt::Display> std::error::Error for Error<C> {}
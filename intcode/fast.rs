@@ -0,0 +1,152 @@
+use std::convert::TryFrom;
+use std::vec::Vec;
+
+use super::digits::Digits;
+use super::opcode::OpInstruction;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded {
+	pub op: OpInstruction,
+	pub modes: [u8; 3],
+}
+
+impl Decoded {
+	pub fn from_word(word: i64) -> Option<Decoded> {
+		let word = u32::try_from(word).ok()?;
+		let op = OpInstruction::from_opcode(word).ok()?;
+		let digits = Digits::from(word);
+
+		let mut modes = [0u8; 3];
+		for offset in 0..op.param_count() {
+			let mode: u32 = digits.subdigits(2+offset..3+offset).into();
+			modes[usize::from(offset)] = u8::try_from(mode).ok()?;
+		}
+
+		Some(Decoded{op, modes})
+	}
+}
+
+
+// Decoded instructions by address. Each entry remembers the word it was
+// decoded from, so a self-modifying write is noticed on the next lookup no
+// matter which path wrote it.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeCache {
+	entries: Vec<Option<(i64, Decoded)>>,
+}
+
+impl DecodeCache {
+	pub fn get(&mut self, pos: usize, word: i64) -> Option<Decoded> {
+		if pos >= self.entries.len() {
+			self.entries.resize(pos+1, None);
+		}
+
+		match self.entries[pos] {
+			Some((cached_word, decoded)) if cached_word == word => Some(decoded),
+			_ => {
+				let decoded = Decoded::from_word(word)?;
+				self.entries[pos] = Some((word, decoded));
+				Some(decoded)
+			}
+		}
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::vec::Vec;
+
+	use super::super::error::{Error, ErrorKind};
+	use super::super::machine::{Arithmetic, Machine, State};
+
+	type Outcome = (Vec<i64>, Result<State, Error>);
+
+	fn outcome(machine: &mut Machine, fast: bool) -> Outcome {
+		let mut outputs = Vec::new();
+		loop {
+			let result = match fast {
+				true => machine.run_fast(),
+				false => machine.run(),
+			};
+			match result {
+				Ok(State::Produced(value)) => outputs.push(value),
+				result => return (outputs, result),
+			}
+		}
+	}
+
+	// Runs the program both ways and checks that they end up in the same
+	// place.
+	fn compare<F: Fn(&mut Machine)>(program: &[i64], setup: F) -> Outcome {
+		let mut slow = Machine::new(program.to_vec());
+		setup(&mut slow);
+		let mut fast = slow.clone();
+
+		let expected = outcome(&mut slow, false);
+		assert_eq!(outcome(&mut fast, true), expected);
+		assert_eq!(fast.memory(), slow.memory());
+		assert_eq!(fast.position(), slow.position());
+		assert_eq!(fast.relative_base(), slow.relative_base());
+		assert_eq!(fast.pending_inputs(), slow.pending_inputs());
+		assert_eq!(fast.instructions_executed(), slow.instructions_executed());
+
+		expected
+	}
+
+	// Adds 3 to the cell at 32, then turns that add into a multiply and
+	// goes around twice more.
+	const SELF_MODIFYING: [i64; 34] = [
+		1001, 30, 1, 30,
+		1, 32, 33, 32,
+		4, 32,
+		1101, 0, 2, 4,
+		1008, 30, 3, 31,
+		1006, 31, 0,
+		99,
+		0, 0, 0, 0, 0, 0, 0, 0,
+		0, 0, 5, 3,
+	];
+
+	#[test]
+	fn self_modifying_code() {
+		let (outputs, state) = compare(&SELF_MODIFYING, |_| ());
+		assert_eq!(outputs, [8, 24, 72]);
+		assert_eq!(state, Ok(State::Halted));
+	}
+
+	#[test]
+	fn budget() {
+		let (outputs, state) = compare(&SELF_MODIFYING, |machine| {
+			machine.set_instruction_budget(Some(9));
+		});
+		assert_eq!(outputs, [8, 24]);
+		assert_eq!(state.map_err(|error| error.kind), Err(ErrorKind::BudgetExhausted(9)));
+	}
+
+	#[test]
+	fn overflow() {
+		let program = [1102, i64::MAX, 2, 5, 99, 0];
+		let (_, state) = compare(&program, |_| ());
+		assert_eq!(state.map_err(|error| (error.pos, error.kind)), Err((0, ErrorKind::Overflow)));
+
+		let (_, state) = compare(&program, |machine| machine.set_arithmetic(Arithmetic::Wrapping));
+		assert_eq!(state, Ok(State::Halted));
+	}
+
+	#[test]
+	fn input_and_memory_growth() {
+		// stores two inputs past the end of the program and outputs their sum
+		let program = [3, 50, 3, 60, 1, 50, 60, 70, 4, 70, 99];
+		let (outputs, state) = compare(&program, |machine| machine.push_input(4));
+		assert_eq!((outputs, state), (vec![], Ok(State::NeedsInput)));
+
+		let (outputs, state) = compare(&program, |machine| {
+			machine.push_input(4);
+			machine.push_input(5);
+		});
+		assert_eq!((outputs, state), (vec![9], Ok(State::Halted)));
+	}
+}
@@ -0,0 +1,465 @@
+// A sparse 2D canvas for programs that draw, with adapters that turn output
+// streams into pixels: a hull-painting robot, a tile screen and an ASCII
+// camera. Canvases render to text, PPM or PNG; y grows downwards.
+
+use std::collections::HashMap;
+use std::io::{self, Write as IoWrite};
+use std::vec::Vec;
+
+use super::error::Error;
+use super::io::{InputSource, OutputSink};
+use super::machine::{Machine, State};
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Coordinate {
+	pub x: i64,
+	pub y: i64,
+}
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Grid {
+	cells: HashMap<Coordinate, i64>,
+}
+
+impl Grid {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn get(&self, coord: Coordinate) -> Option<i64> {
+		self.cells.get(&coord).cloned()
+	}
+
+	pub fn set(&mut self, coord: Coordinate, value: i64) {
+		self.cells.insert(coord, value);
+	}
+
+	pub fn len(&self) -> usize {
+		self.cells.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.cells.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=(&Coordinate, &i64)> {
+		self.cells.iter()
+	}
+
+	// Smallest and largest corners of the cells set so far.
+	pub fn bounds(&self) -> Option<(Coordinate, Coordinate)> {
+		let mut coords = self.cells.keys();
+		let first = *coords.next()?;
+		Some(coords.fold((first, first), |(low, high), c| (
+			Coordinate{x: low.x.min(c.x), y: low.y.min(c.y)},
+			Coordinate{x: high.x.max(c.x), y: high.y.max(c.y)},
+		)))
+	}
+
+	// Cell values row by row over the bounds; `None` where nothing was set.
+	fn rows(&self) -> Vec<Vec<Option<i64>>> {
+		let (low, high) = match self.bounds() {
+			Some(bounds) => bounds,
+			None => return Vec::new(),
+		};
+
+		(low.y..=high.y).map(|y| {
+			(low.x..=high.x).map(|x| self.get(Coordinate{x, y})).collect()
+		}).collect()
+	}
+
+	pub fn render<F: Fn(Option<i64>) -> char>(&self, glyph: F) -> String {
+		let mut text = String::new();
+		for row in self.rows() {
+			text.extend(row.into_iter().map(&glyph));
+			text.push('\n');
+		}
+		text
+	}
+
+	fn pixels<F>(&self, color: F, scale: usize) -> (usize, usize, Vec<Vec<u8>>)
+	where
+		F: Fn(Option<i64>) -> [u8; 3],
+	{
+		let scale = scale.max(1);
+		let mut lines = Vec::<Vec<u8>>::new();
+		for row in self.rows() {
+			let mut line = Vec::<u8>::new();
+			for value in row {
+				for _ in 0..scale {
+					line.extend_from_slice(&color(value));
+				}
+			}
+			for _ in 0..scale {
+				lines.push(line.clone());
+			}
+		}
+
+		let width = lines.first().map_or(0, |line| line.len() / 3);
+		(width, lines.len(), lines)
+	}
+
+	// Binary PPM (P6), each cell drawn as a `scale`-sized square.
+	pub fn write_ppm<W, F>(&self, writer: &mut W, color: F, scale: usize) -> io::Result<()>
+	where
+		W: IoWrite,
+		F: Fn(Option<i64>) -> [u8; 3],
+	{
+		let (width, height, lines) = self.pixels(color, scale);
+		write!(writer, "P6\n{} {}\n255\n", width, height)?;
+		for line in lines {
+			writer.write_all(&line)?;
+		}
+		Ok(())
+	}
+
+	// 8-bit RGB PNG, each cell drawn as a `scale`-sized square.
+	pub fn write_png<W, F>(&self, writer: &mut W, color: F, scale: usize) -> io::Result<()>
+	where
+		W: IoWrite,
+		F: Fn(Option<i64>) -> [u8; 3],
+	{
+		let (width, height, lines) = self.pixels(color, scale);
+		let mut raw = Vec::<u8>::new();
+		for line in lines {
+			raw.push(0); // filter type: none
+			raw.extend_from_slice(&line);
+		}
+
+		let mut header = Vec::<u8>::new();
+		header.extend_from_slice(&(width as u32).to_be_bytes());
+		header.extend_from_slice(&(height as u32).to_be_bytes());
+		// bit depth, colour type (RGB), compression, filter, interlace
+		header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+		writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+		write_chunk(writer, b"IHDR", &header)?;
+		write_chunk(writer, b"IDAT", &zlib_stored(&raw))?;
+		write_chunk(writer, b"IEND", &[])
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+fn crc32(bytes: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in bytes {
+		crc ^= u32::from(byte);
+		for _ in 0..8 {
+			crc = match crc & 1 {
+				1 => (crc >> 1) ^ 0xedb8_8320,
+				_ => crc >> 1,
+			};
+		}
+	}
+	!crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+	let (mut a, mut b) = (1u32, 0u32);
+	for &byte in bytes {
+		a = (a + u32::from(byte)) % 65521;
+		b = (b + a) % 65521;
+	}
+	(b << 16) | a
+}
+
+// A zlib stream of uncompressed deflate blocks; PNG doesn't require that
+// the data actually be compressed.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+	let mut out = vec![0x78, 0x01];
+	let mut blocks = data.chunks(0xffff).peekable();
+	if blocks.peek().is_none() {
+		out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+	}
+	while let Some(block) = blocks.next() {
+		let last = blocks.peek().is_none() as u8;
+		let len = block.len() as u16;
+		out.push(last);
+		out.extend_from_slice(&len.to_le_bytes());
+		out.extend_from_slice(&(!len).to_le_bytes());
+		out.extend_from_slice(block);
+	}
+	out.extend_from_slice(&adler32(data).to_be_bytes());
+	out
+}
+
+fn write_chunk<W: IoWrite>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+	let mut body = kind.to_vec();
+	body.extend_from_slice(data);
+
+	writer.write_all(&(data.len() as u32).to_be_bytes())?;
+	writer.write_all(&body)?;
+	writer.write_all(&crc32(&body).to_be_bytes())
+}
+
+//-----------------------------------------------------------------------------
+
+// Reads the colour under it, then takes output pairs of (colour to paint,
+// turn: 0 = left, 1 = right) and moves one panel forward after each.
+#[derive(Debug, Clone, Default)]
+pub struct PaintRobot {
+	pub grid: Grid,
+	pub position: Coordinate,
+	// as (dx, dy); starts facing up
+	direction: (i64, i64),
+	pending: Option<i64>,
+}
+
+impl PaintRobot {
+	pub fn new() -> Self {
+		Self{direction: (0, -1), ..Self::default()}
+	}
+
+	pub fn glyph(value: Option<i64>) -> char {
+		match value {
+			Some(1) => '#',
+			_ => '.',
+		}
+	}
+
+	pub fn color(value: Option<i64>) -> [u8; 3] {
+		match value {
+			Some(1) => [255, 255, 255],
+			_ => [0, 0, 0],
+		}
+	}
+
+	pub fn run(&mut self, machine: &mut Machine) -> Result<State, Error> {
+		machine.drive(self, Machine::run)
+	}
+}
+
+impl InputSource for PaintRobot {
+	fn next_input(&mut self) -> Option<i64> {
+		Some(self.grid.get(self.position).unwrap_or(0))
+	}
+}
+
+impl OutputSink for PaintRobot {
+	fn put_output(&mut self, value: i64) {
+		let color = match self.pending.take() {
+			Some(color) => color,
+			None => {
+				self.pending = Some(value);
+				return;
+			},
+		};
+
+		self.grid.set(self.position, color);
+		let (dx, dy) = self.direction;
+		self.direction = match value {
+			0 => (dy, -dx),
+			_ => (-dy, dx),
+		};
+		self.position.x += self.direction.0;
+		self.position.y += self.direction.1;
+	}
+}
+
+
+// Takes output triples of (x, y, tile); (-1, 0, n) sets the score instead.
+#[derive(Debug, Clone, Default)]
+pub struct TileScreen {
+	pub grid: Grid,
+	pub score: Option<i64>,
+	pending: Vec<i64>,
+}
+
+impl TileScreen {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn glyph(value: Option<i64>) -> char {
+		match value {
+			Some(1) => '#',
+			Some(2) => '=',
+			Some(3) => '-',
+			Some(4) => 'o',
+			_ => ' ',
+		}
+	}
+
+	pub fn color(value: Option<i64>) -> [u8; 3] {
+		match value {
+			Some(1) => [128, 128, 128],
+			Some(2) => [200, 80, 40],
+			Some(3) => [255, 255, 255],
+			Some(4) => [255, 220, 0],
+			_ => [0, 0, 0],
+		}
+	}
+}
+
+impl OutputSink for TileScreen {
+	fn put_output(&mut self, value: i64) {
+		self.pending.push(value);
+		if self.pending.len() < 3 {
+			return;
+		}
+
+		match self.pending[..] {
+			[-1, 0, score] => self.score = Some(score),
+			[x, y, tile] => self.grid.set(Coordinate{x, y}, tile),
+			_ => unreachable!(),
+		};
+		self.pending.clear();
+	}
+}
+
+
+// Takes ASCII output, one character per cell and a newline per row. An
+// empty line ends the current frame.
+#[derive(Debug, Clone, Default)]
+pub struct CameraFrame {
+	pub grid: Grid,
+	pub frames: Vec<Grid>,
+	cursor: Coordinate,
+}
+
+impl CameraFrame {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn glyph(value: Option<i64>) -> char {
+		match value {
+			Some(code @ 32..=126) => code as u8 as char,
+			_ => ' ',
+		}
+	}
+
+	pub fn color(value: Option<i64>) -> [u8; 3] {
+		match value.map(|code| code as u8 as char) {
+			Some('.') | Some(' ') | None => [0, 0, 0],
+			Some('#') => [255, 255, 255],
+			Some(_) => [255, 64, 64],
+		}
+	}
+
+	// The last completed frame, or the one being drawn if there is none.
+	pub fn latest(&self) -> &Grid {
+		self.frames.last().unwrap_or(&self.grid)
+	}
+}
+
+impl OutputSink for CameraFrame {
+	fn put_output(&mut self, value: i64) {
+		if value != 10 {
+			self.grid.set(self.cursor, value);
+			self.cursor.x += 1;
+			return;
+		}
+
+		if self.cursor.x == 0 && !self.grid.is_empty() {
+			self.frames.push(std::mem::replace(&mut self.grid, Grid::new()));
+			self.cursor.y = 0;
+		} else {
+			self.cursor = Coordinate{x: 0, y: self.cursor.y + 1};
+		}
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{adler32, crc32, CameraFrame, Coordinate, Grid, PaintRobot, TileScreen};
+	use super::super::asm::assemble;
+	use super::super::io::OutputSink;
+	use super::super::machine::{Machine, State};
+
+	fn grid(cells: &[(i64, i64, i64)]) -> Grid {
+		let mut grid = Grid::new();
+		for &(x, y, value) in cells {
+			grid.set(Coordinate{x, y}, value);
+		}
+		grid
+	}
+
+	#[test]
+	fn renders_within_bounds() {
+		let grid = grid(&[(-1, 2, 1), (1, 3, 0)]);
+		assert_eq!(grid.bounds(), Some((Coordinate{x: -1, y: 2}, Coordinate{x: 1, y: 3})));
+		assert_eq!(grid.render(PaintRobot::glyph), "#..\n...\n");
+		assert_eq!(grid.render(|value| match value {
+			Some(_) => 'x',
+			None => '?',
+		}), "x??\n??x\n");
+		assert_eq!(Grid::new().render(PaintRobot::glyph), "");
+	}
+
+	#[test]
+	fn paint_robot() {
+		// the day 11 example: paint and turn, reading the panel each time
+		let mut source = String::new();
+		for (color, turn) in [(1, 0), (0, 0), (1, 0), (1, 0), (0, 1), (1, 0), (1, 0)].iter() {
+			source += &format!("in [t]\nout #{}\nout #{}\n", color, turn);
+		}
+		source += "hlt\nt: data 0";
+		let mut machine = Machine::new(assemble(&source).unwrap());
+
+		let mut robot = PaintRobot::new();
+		assert_eq!(robot.run(&mut machine), Ok(State::Halted));
+		assert_eq!(robot.grid.len(), 6);
+		assert_eq!(robot.position, Coordinate{x: 0, y: -1});
+		assert_eq!(robot.grid.render(PaintRobot::glyph), "..#\n..#\n##.\n");
+	}
+
+	#[test]
+	fn tile_screen_and_camera() {
+		let mut screen = TileScreen::new();
+		for &value in [0, 0, 1, 2, 0, 4, -1, 0, 1234].iter() {
+			screen.put_output(value);
+		}
+		assert_eq!(screen.score, Some(1234));
+		assert_eq!(screen.grid.render(TileScreen::glyph), "# o\n");
+
+		let mut camera = CameraFrame::new();
+		for &byte in b"#.\n.^\n\n##\n".iter() {
+			camera.put_output(i64::from(byte));
+		}
+		assert_eq!(camera.frames.len(), 1);
+		assert_eq!(camera.latest().render(CameraFrame::glyph), "#.\n.^\n");
+		assert_eq!(camera.grid.render(CameraFrame::glyph), "##\n");
+	}
+
+	#[test]
+	fn checksums() {
+		assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+		assert_eq!(crc32(b"IEND"), 0xae42_6082);
+		assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+	}
+
+	#[test]
+	fn png_header_and_chunks() {
+		let mut png = Vec::new();
+		grid(&[(0, 0, 1), (2, 0, 0)]).write_png(&mut png, PaintRobot::color, 2).unwrap();
+
+		assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+		assert_eq!(&png[8..16], b"\0\0\0\x0dIHDR");
+		// 3 by 1 cells, scaled up to 6 by 2 pixels
+		assert_eq!(&png[16..24], &[0, 0, 0, 6, 0, 0, 0, 2]);
+		assert_eq!(&png[24..29], &[8, 2, 0, 0, 0]);
+		assert_eq!(&png[29..33], &crc32(&png[12..29]).to_be_bytes());
+
+		// one filter byte and 18 bytes of pixels per row, stored uncompressed
+		assert_eq!(&png[33..41], b"\0\0\0\x31IDAT");
+		let data = &png[41..41+0x31];
+		assert_eq!(&data[..7], &[0x78, 0x01, 1, 38, 0, !38, 0xff]);
+		assert_eq!(&data[7..13], &[0, 255, 255, 255, 255, 255]);
+		assert_eq!(&png[41+0x31..45+0x31], &crc32(&png[37..41+0x31]).to_be_bytes());
+
+		assert_eq!(&png[png.len()-12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+	}
+
+	#[test]
+	fn ppm() {
+		let mut ppm = Vec::new();
+		grid(&[(0, 0, 1), (1, 0, 0)]).write_ppm(&mut ppm, PaintRobot::color, 1).unwrap();
+		assert_eq!(ppm, b"P6\n2 1\n255\n\xff\xff\xff\0\0\0");
+	}
+}
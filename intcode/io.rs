@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, Sender};
+use std::vec::Vec;
+
+
+// `C` is the machine's cell type; see `Cell`.
+pub trait InputSource<C = i64> {
+	// `None` means no value is available (yet); the machine stays paused.
+	fn next_input(&mut self) -> Option<C>;
+}
+
+pub trait OutputSink<C = i64> {
+	fn put_output(&mut self, value: C);
+}
+
+//-----------------------------------------------------------------------------
+
+impl<C> InputSource<C> for VecDeque<C> {
+	fn next_input(&mut self) -> Option<C> {
+		self.pop_front()
+	}
+}
+
+impl<C> OutputSink<C> for VecDeque<C> {
+	fn put_output(&mut self, value: C) {
+		self.push_back(value);
+	}
+}
+
+impl<C> InputSource<C> for std::vec::IntoIter<C> {
+	fn next_input(&mut self) -> Option<C> {
+		self.next()
+	}
+}
+
+impl<C> OutputSink<C> for Vec<C> {
+	fn put_output(&mut self, value: C) {
+		self.push(value);
+	}
+}
+
+// An input and an output taken together, as `Machine::drive` wants them.
+impl<'a, C, I, O> InputSource<C> for (&'a mut I, &'a mut O)
+where
+	I: InputSource<C> + ?Sized,
+	O: ?Sized,
+{
+	fn next_input(&mut self) -> Option<C> {
+		self.0.next_input()
+	}
+}
+
+impl<'a, C, I, O> OutputSink<C> for (&'a mut I, &'a mut O)
+where
+	I: ?Sized,
+	O: OutputSink<C> + ?Sized,
+{
+	fn put_output(&mut self, value: C) {
+		self.1.put_output(value);
+	}
+}
+
+impl<C> InputSource<C> for Receiver<C> {
+	fn next_input(&mut self) -> Option<C> {
+		self.recv().ok()
+	}
+}
+
+impl<C> OutputSink<C> for Sender<C> {
+	fn put_output(&mut self, value: C) {
+		// a hung-up receiver just means nobody is listening anymore
+		self.send(value).ok();
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub struct IterInput<I>(pub I);
+
+impl<C, I: Iterator<Item=C>> InputSource<C> for IterInput<I> {
+	fn next_input(&mut self) -> Option<C> {
+		self.0.next()
+	}
+}
+
+
+// One integer per line; blank lines are ignored, and lines that aren't
+// integers are skipped and kept for the caller to report.
+#[derive(Debug)]
+pub struct ReaderInput<R> {
+	reader: R,
+	buffer: String,
+	line: usize,
+	rejected: Vec<(usize, String)>,
+}
+
+impl<R: BufRead> ReaderInput<R> {
+	pub fn new(reader: R) -> Self {
+		Self{reader, buffer: String::new(), line: 0, rejected: Vec::new()}
+	}
+
+	// The skipped lines so far, with their one-based line numbers.
+	pub fn rejected(&self) -> &[(usize, String)] {
+		&self.rejected
+	}
+}
+
+impl<C: FromStr, R: BufRead> InputSource<C> for ReaderInput<R> {
+	fn next_input(&mut self) -> Option<C> {
+		loop {
+			self.buffer.clear();
+			match self.reader.read_line(&mut self.buffer) {
+				Ok(0) | Err(_) => return None,
+				Ok(_) => self.line += 1,
+			};
+
+			let text = self.buffer.trim();
+			if text.is_empty() {
+				continue;
+			}
+			match text.parse::<C>() {
+				Ok(value) => return Some(value),
+				Err(_) => self.rejected.push((self.line, text.to_string())),
+			};
+		}
+	}
+}
+
+
+#[derive(Debug)]
+pub struct WriterOutput<W>(pub W);
+
+impl<C: Display, W: Write> OutputSink<C> for WriterOutput<W> {
+	fn put_output(&mut self, value: C) {
+		writeln!(self.0, "{}", value).expect("failed to write output");
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug, Default)]
+pub struct Terminal;
+
+// Asks again until it gets an integer; `None` once stdin is closed.
+impl<C: FromStr> InputSource<C> for Terminal {
+	fn next_input(&mut self) -> Option<C> {
+		println!("Enter in an input value");
+		loop {
+			let mut buffer = String::new();
+			match std::io::stdin().read_line(&mut buffer) {
+				Ok(0) | Err(_) => return None,
+				Ok(_) => {},
+			};
+
+			let text = buffer.trim();
+			match text.parse::<C>() {
+				Ok(value) => return Some(value),
+				Err(_) if text.is_empty() => {},
+				Err(_) => println!("'{}' is not an integer; try again", text),
+			};
+		}
+	}
+}
+
+impl<C: Display> OutputSink<C> for Terminal {
+	fn put_output(&mut self, value: C) {
+		println!("{}", value);
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::collections::VecDeque;
+
+	use super::ReaderInput;
+	use super::super::machine::{Machine, State};
+
+	fn run(program: &[i64], input: i64) -> Vec<i64> {
+		let mut inputs: VecDeque<i64> = vec![input].into_iter().collect();
+		let mut outputs = Vec::<i64>::new();
+		let state = Machine::new(program.to_vec()).run_with(&mut inputs, &mut outputs);
+
+		assert_eq!(state, Ok(State::Halted));
+		outputs
+	}
+
+	// the comparison and jump examples from day 5, part 2
+	#[test]
+	fn day5_compare_examples() {
+		let equal_position = [3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+		let less_position = [3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+		let equal_immediate = [3, 3, 1108, -1, 8, 3, 4, 3, 99];
+		let less_immediate = [3, 3, 1107, -1, 8, 3, 4, 3, 99];
+
+		for &input in [-3, 7, 8, 9].iter() {
+			let equal = vec![(input == 8) as i64];
+			let less = vec![(input < 8) as i64];
+			assert_eq!(run(&equal_position, input), equal);
+			assert_eq!(run(&equal_immediate, input), equal);
+			assert_eq!(run(&less_position, input), less);
+			assert_eq!(run(&less_immediate, input), less);
+		}
+	}
+
+	#[test]
+	fn day5_jump_examples() {
+		let jump_position = [3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+		let jump_immediate = [3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+		let compare_to_8 = [
+			3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31,
+			1106, 0, 36, 98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104,
+			999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20, 1105, 1, 46, 98, 99,
+		];
+
+		for &input in [0, 5].iter() {
+			let nonzero = vec![(input != 0) as i64];
+			assert_eq!(run(&jump_position, input), nonzero);
+			assert_eq!(run(&jump_immediate, input), nonzero);
+		}
+		assert_eq!(run(&compare_to_8, 7), vec![999]);
+		assert_eq!(run(&compare_to_8, 8), vec![1000]);
+		assert_eq!(run(&compare_to_8, 9), vec![1001]);
+	}
+
+	#[test]
+	fn paused_without_input() {
+		let mut outputs = Vec::<i64>::new();
+		let state = Machine::new(vec![3, 0, 4, 0, 99])
+			.run_with(&mut VecDeque::new(), &mut outputs);
+
+		assert_eq!(state, Ok(State::NeedsInput));
+		assert!(outputs.is_empty());
+	}
+
+	#[test]
+	fn reader_skips_bad_lines() {
+		let mut input = ReaderInput::new("5\n\nfive\n-7\n".as_bytes());
+		let mut outputs = Vec::<i64>::new();
+		let state = Machine::new(vec![3, 0, 3, 1, 1, 0, 1, 0, 4, 0, 99])
+			.run_with(&mut input, &mut outputs);
+
+		assert_eq!(state, Ok(State::Halted));
+		assert_eq!(outputs, vec![-2]);
+		assert_eq!(input.rejected(), &[(3, "five".to_string())]);
+	}
+}
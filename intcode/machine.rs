@@ -0,0 +1,798 @@
+use std::cmp::min;
+use std::convert::TryFrom;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::vec::Vec;
+
+use super::cell::Cell;
+use super::digits::Digits;
+use super::error::{Error, ErrorKind};
+use super::fast::DecodeCache;
+use super::io::{InputSource, OutputSink};
+use super::memory::Memory;
+use super::opcode::OpInstruction;
+use super::registry::{Flow, Registry};
+use super::snapshot::Snapshot;
+use super::params::{get_param_ref, get_param_addr};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State<C = i64> {
+	Running,
+	NeedsInput,
+	Produced(C),
+	Halted,
+	// halted by an instruction that returned a value, e.g. an exit code
+	Exited(C),
+}
+
+
+// How add and multiply treat results that don't fit in an i64. For values
+// of any size, use a `Machine<BigInt>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+	// fail with an overflow error at the offending instruction
+	#[default]
+	Checked,
+	// wrap around in two's complement
+	Wrapping,
+}
+
+impl Arithmetic {
+	#[inline]
+	pub fn add(self, a: i64, b: i64) -> Option<i64> {
+		match self {
+			Self::Checked => a.checked_add(b),
+			Self::Wrapping => Some(a.wrapping_add(b)),
+		}
+	}
+
+	#[inline]
+	pub fn mul(self, a: i64, b: i64) -> Option<i64> {
+		match self {
+			Self::Checked => a.checked_mul(b),
+			Self::Wrapping => Some(a.wrapping_mul(b)),
+		}
+	}
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Write<C = i64> {
+	pub addr: usize,
+	pub old: C,
+	pub new: C,
+}
+
+// What the most recently executed instruction did. `values` holds the
+// resolved operands: the value read for inputs, the address for writes.
+// Bit `i` of `resolved` is set if operand `i` was resolved at all; a jump
+// that isn't taken never reads its target, so that value stays zero.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Effect<C = i64> {
+	pub pos: usize,
+	pub word: C,
+	pub op: Option<OpInstruction>,
+	// from the instruction set, so custom instructions have them too
+	pub mnemonic: &'static str,
+	pub arity: u8,
+	pub values: [C; 3],
+	pub resolved: u8,
+	pub relative_base: i64,
+	pub memory_len: usize,
+	pub write: Option<Write<C>>,
+	pub input: Option<C>,
+	pub output: Option<C>,
+	// counted in `instructions_executed`; halts and exits are not
+	pub counted: bool,
+}
+
+
+// Brent's cycle detection over the full machine state: compare against a
+// saved state, re-saving it whenever the distance reaches a power of two.
+#[derive(Debug, Clone)]
+struct LoopDetector<C> {
+	seen: Option<u64>,
+	power: u64,
+	distance: u64,
+	pos: usize,
+	relative_base: i64,
+	memory: Memory<C>,
+	inputs: VecDeque<C>,
+}
+
+
+// An Intcode machine whose memory holds cells of type `C`; see `Cell`.
+#[derive(Debug, Clone)]
+pub struct Machine<C = i64> {
+	memory: Memory<C>,
+	pos: usize,
+	relative_base: i64,
+	inputs: VecDeque<C>,
+	effect: Effect<C>,
+	// whether `effect` describes the latest step
+	has_effect: bool,
+	executed: u64,
+	budget: Option<u64>,
+	loop_detector: Option<LoopDetector<C>>,
+	decode_cache: DecodeCache,
+	registry: Arc<Registry<C>>,
+	arithmetic: Arithmetic,
+	// whether `registry` is the shared core set, which `run_fast` hardcodes
+	core_registry: bool,
+}
+
+impl<C: Cell> Machine<C> {
+	pub fn new(program: Vec<C>) -> Self {
+		Self::with_registry(program, Registry::shared_core())
+	}
+
+	pub fn with_registry(program: Vec<C>, registry: Arc<Registry<C>>) -> Self {
+		let mut machine = Self::from_memory(Memory::new(program));
+		machine.set_registry(registry);
+		machine
+	}
+
+	// For picking the memory backend, e.g. `Memory::sparse(program)`.
+	pub fn from_memory(memory: Memory<C>) -> Self {
+		let registry = Registry::shared_core();
+		Self{
+			memory,
+			pos: 0,
+			relative_base: 0,
+			inputs: VecDeque::new(),
+			effect: Effect::default(),
+			has_effect: false,
+			executed: 0,
+			budget: None,
+			loop_detector: None,
+			decode_cache: DecodeCache::default(),
+			registry,
+			arithmetic: Arithmetic::Checked,
+			core_registry: true,
+		}
+	}
+
+	pub fn registry(&self) -> &Registry<C> {
+		&self.registry
+	}
+
+	pub fn arithmetic(&self) -> Arithmetic {
+		self.arithmetic
+	}
+
+	pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+		self.arithmetic = arithmetic;
+	}
+
+	pub fn set_registry(&mut self, registry: Arc<Registry<C>>) {
+		self.core_registry = Arc::ptr_eq(&registry, &Registry::shared_core());
+		self.registry = registry;
+	}
+
+	// A copy of all of memory.
+	pub fn program(&self) -> Vec<C> {
+		self.memory.to_vec()
+	}
+
+	pub fn into_program(self) -> Vec<C> {
+		self.memory.into_vec()
+	}
+
+	pub fn memory(&self) -> &Memory<C> {
+		&self.memory
+	}
+
+	pub fn memory_mut(&mut self) -> &mut Memory<C> {
+		&mut self.memory
+	}
+
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+
+	pub fn relative_base(&self) -> i64 {
+		self.relative_base
+	}
+
+	pub fn push_input(&mut self, value: C) {
+		self.inputs.push_back(value);
+	}
+
+	pub fn pending_inputs(&self) -> &VecDeque<C> {
+		&self.inputs
+	}
+
+	pub fn instructions_executed(&self) -> u64 {
+		self.executed
+	}
+
+	// The machine refuses to run more than `budget` instructions in total.
+	pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+		self.budget = budget;
+	}
+
+	// Exact, but each step compares against a saved copy of memory.
+	pub fn set_loop_detection(&mut self, enabled: bool) {
+		self.loop_detector = match enabled {
+			false => None,
+			true => Some(LoopDetector{
+				seen: None,
+				power: 1,
+				distance: 0,
+				pos: 0,
+				relative_base: 0,
+				memory: Memory::new(Vec::new()),
+				inputs: VecDeque::new(),
+			}),
+		};
+	}
+
+	// Captures the machine so it can be rolled back to or saved to a file.
+	pub fn snapshot(&self) -> Snapshot<C> {
+		Snapshot{
+			pos: self.pos,
+			relative_base: self.relative_base,
+			executed: self.executed,
+			budget: self.budget,
+			loop_detection: self.loop_detector.is_some(),
+			memory_limit: self.memory.limit(),
+			memory_kind: self.memory.kind(),
+			arithmetic: self.arithmetic,
+			inputs: self.inputs.iter().cloned().collect(),
+			memory_len: self.memory.len(),
+			memory: self.memory.runs(),
+		}
+	}
+
+	pub fn from_snapshot(snapshot: Snapshot<C>) -> Self {
+		let mut machine = Self::from_memory(Memory::from_runs(
+			snapshot.memory_len, &snapshot.memory, snapshot.memory_kind
+		));
+		machine.memory.set_limit(snapshot.memory_limit);
+		machine.pos = snapshot.pos;
+		machine.relative_base = snapshot.relative_base;
+		machine.inputs = snapshot.inputs.into_iter().collect();
+		machine.executed = snapshot.executed;
+		machine.budget = snapshot.budget;
+		machine.arithmetic = snapshot.arithmetic;
+		machine.set_loop_detection(snapshot.loop_detection);
+
+		machine
+	}
+
+	// Keeps the machine's instruction set.
+	pub fn restore(&mut self, snapshot: Snapshot<C>) {
+		let registry = self.registry.clone();
+		*self = Self::from_snapshot(snapshot);
+		self.set_registry(registry);
+	}
+
+	pub fn run(&mut self) -> Result<State<C>, Error<C>> {
+		loop {
+			match self.step()? {
+				State::Running => continue,
+				state => return Ok(state),
+			}
+		}
+	}
+
+	pub fn run_with<I, O>(&mut self, input: &mut I, output: &mut O)
+	-> Result<State<C>, Error<C>>
+	where
+		I: InputSource<C> + ?Sized,
+		O: OutputSink<C> + ?Sized,
+	{
+		self.drive(&mut (input, output), Self::run)
+	}
+
+	// Advances the machine with `run`, which is `Machine::run` or something
+	// wrapping it, feeding it from `io` and handing `io` its outputs, until
+	// it stops for any other reason or `io` has no input left.
+	pub fn drive<T, F>(&mut self, io: &mut T, mut run: F) -> Result<State<C>, Error<C>>
+	where
+		T: InputSource<C> + OutputSink<C> + ?Sized,
+		F: FnMut(&mut Self) -> Result<State<C>, Error<C>>,
+	{
+		loop {
+			match run(self)? {
+				State::NeedsInput => match io.next_input() {
+					Some(value) => self.push_input(value),
+					None => return Ok(State::NeedsInput),
+				},
+				State::Produced(value) => io.put_output(value),
+				state => return Ok(state),
+			}
+		}
+	}
+
+	// What the latest step did; `None` if it executed nothing, because it
+	// ran off the end of memory, waited for input or failed.
+	pub fn last_effect(&self) -> Option<&Effect<C>> {
+		match self.has_effect {
+			true => Some(&self.effect),
+			false => None,
+		}
+	}
+
+	// On error the machine is left untouched at the failing instruction.
+	pub fn step(&mut self) -> Result<State<C>, Error<C>> {
+		let pos = self.pos;
+		self.has_effect = false;
+
+		if pos >= self.memory.len() {
+			return Ok(State::Halted);
+		}
+		let word = self.memory.peek(pos);
+		let error = |kind| Error{pos, word: word.clone(), kind};
+
+		if let Some(budget) = self.budget {
+			if self.executed >= budget {
+				return Err(error(ErrorKind::BudgetExhausted(budget)));
+			}
+		}
+		self.check_loop().map_err(error)?;
+
+		self.effect = Effect{
+			pos,
+			word: word.clone(),
+			relative_base: self.relative_base,
+			memory_len: self.memory.len(),
+			..Effect::default()
+		};
+
+		let state = self.exec(pos, &word).map_err(error)?;
+		if let State::Running | State::Produced(_) = state {
+			self.executed += 1;
+			self.effect.counted = true;
+		}
+		self.has_effect = state != State::NeedsInput;
+
+		Ok(state)
+	}
+
+	// Reverts the instruction that produced `effect`, which must be the most
+	// recent one still in effect. Outputs it produced cannot be taken back.
+	pub fn unstep(&mut self, effect: &Effect<C>) {
+		self.memory.truncate(effect.memory_len);
+		if let Some(write) = effect.write.as_ref().filter(|write| write.addr < effect.memory_len) {
+			if let Ok(cell) = self.memory.get_mut(write.addr) {
+				*cell = write.old.clone();
+			}
+		}
+		if let Some(value) = &effect.input {
+			self.inputs.push_front(value.clone());
+		}
+
+		self.pos = effect.pos;
+		self.relative_base = effect.relative_base;
+		if effect.counted {
+			self.executed -= 1;
+		}
+		// a saved state from the undone future would report bogus loops
+		if self.loop_detector.is_some() {
+			self.set_loop_detection(true);
+		}
+	}
+
+	fn check_loop(&mut self) -> Result<(), ErrorKind<C>> {
+		let detector = match self.loop_detector.as_mut() {
+			Some(detector) => detector,
+			None => return Ok(()),
+		};
+		// only look at states that an executed instruction produced
+		if detector.seen == Some(self.executed) {
+			return Ok(());
+		}
+		detector.seen = Some(self.executed);
+
+		if detector.distance > 0
+			&& detector.pos == self.pos
+			&& detector.relative_base == self.relative_base
+			&& detector.inputs == self.inputs
+			&& detector.memory == self.memory
+		{
+			return Err(ErrorKind::InfiniteLoop{period: detector.distance});
+		}
+
+		if detector.distance == detector.power {
+			detector.power *= 2;
+			detector.distance = 0;
+			detector.pos = self.pos;
+			detector.relative_base = self.relative_base;
+			detector.memory.clone_from(&self.memory);
+			detector.inputs.clone_from(&self.inputs);
+		}
+		detector.distance += 1;
+
+		Ok(())
+	}
+
+	fn param(&mut self, pos: usize, offset: u8) -> Result<C, ErrorKind<C>> {
+		let value = get_param_ref(&self.memory, self.relative_base, pos, offset)?;
+		self.effect.values[usize::from(offset)] = value.clone();
+		self.effect.resolved |= 1 << offset;
+
+		Ok(value)
+	}
+
+	fn write_param(&mut self, pos: usize, offset: u8, value: C)
+	-> Result<(), ErrorKind<C>> {
+		let addr = get_param_addr(&self.memory, self.relative_base, pos, offset)?;
+		let cell = self.memory.get_mut(addr)?;
+
+		self.effect.values[usize::from(offset)] = C::from(addr as i64);
+		self.effect.resolved |= 1 << offset;
+		let old = std::mem::replace(cell, value.clone());
+		self.effect.write = Some(Write{addr, old, new: value});
+
+		Ok(())
+	}
+
+	fn exec(&mut self, pos: usize, word: &C) -> Result<State<C>, ErrorKind<C>> {
+		let op_modes = word.to_i64().and_then(|w| u32::try_from(w).ok()).ok_or(
+			ErrorKind::InvalidOpcode(word.clone())
+		)?;
+		let opcode: u32 = Digits::from(op_modes).subdigits(..2).into();
+		let registry = self.registry.clone();
+		let spec = registry.get(opcode).ok_or(
+			ErrorKind::InvalidOpcode(C::from(i64::from(opcode)))
+		)?;
+		self.effect.op = spec.op;
+		self.effect.mnemonic = spec.mnemonic;
+		self.effect.arity = spec.arity;
+
+		let flow = (spec.semantics)(&mut Exec{
+			machine: self,
+			pos,
+			arity: spec.arity,
+			write_param: spec.write_param,
+		})?;
+		let next = pos + 1 + usize::from(spec.arity);
+
+		match flow {
+			Flow::Next => self.pos = next,
+			// only falling off the end halts; a jump must land on a loaded
+			// or written cell
+			Flow::Jump(target) => {
+				self.pos = target.to_i64()
+					.and_then(|t| usize::try_from(t).ok())
+					.filter(|&t| t < min(self.memory.len(), self.memory.limit()))
+					.ok_or(ErrorKind::BadJumpTarget(target))?;
+			}
+			Flow::NeedsInput => return Ok(State::NeedsInput),
+			Flow::Produced(value) => {
+				self.effect.output = Some(value.clone());
+				self.pos = next;
+				return Ok(State::Produced(value));
+			}
+			Flow::Halt => return Ok(State::Halted),
+			Flow::Exit(value) => return Ok(State::Exited(value)),
+		};
+
+		Ok(State::Running)
+	}
+}
+
+
+// An instruction's view of the machine while its semantics run. Parameters
+// are read and written through their modes; everything read or written is
+// recorded in the machine's `last_effect`.
+pub struct Exec<'a, C = i64> {
+	machine: &'a mut Machine<C>,
+	pos: usize,
+	arity: u8,
+	write_param: Option<u8>,
+}
+
+impl<'a, C: Cell> Exec<'a, C> {
+	pub fn position(&self) -> usize {
+		self.pos
+	}
+
+	pub fn memory(&self) -> &Memory<C> {
+		&self.machine.memory
+	}
+
+	pub fn read(&mut self, param: u8) -> Result<C, ErrorKind<C>> {
+		assert!(param < self.arity, "parameter {} out of range", param);
+		self.machine.param(self.pos, param)
+	}
+
+	// Only the declared write parameter can be written, once all reads that
+	// can fail are done; the machine must be untouched if the step fails.
+	pub fn write(&mut self, param: u8, value: C) -> Result<(), ErrorKind<C>> {
+		assert!(self.write_param == Some(param), "parameter {} is not a write", param);
+		self.machine.write_param(self.pos, param, value)
+	}
+
+	pub fn peek_input(&self) -> Option<&C> {
+		self.machine.inputs.front()
+	}
+
+	pub fn take_input(&mut self) -> Option<C> {
+		let value = self.machine.inputs.pop_front()?;
+		self.machine.effect.input = Some(value.clone());
+		Some(value)
+	}
+
+	pub fn relative_base(&self) -> i64 {
+		self.machine.relative_base
+	}
+
+	pub fn arithmetic(&self) -> Arithmetic {
+		self.machine.arithmetic
+	}
+
+	pub fn set_relative_base(&mut self, base: i64) {
+		self.machine.relative_base = base;
+	}
+}
+
+
+// The fast path works on plain i64 words only.
+impl Machine {
+	// Behaves exactly like `run`, but executes from pre-decoded instructions
+	// and records no `last_effect`. Anything unusual (errors, memory
+	// growth, waiting for input) is handed to `step`. Machines with a custom
+	// instruction set always take the slow path.
+	pub fn run_fast(&mut self) -> Result<State, Error> {
+		if self.loop_detector.is_some() || !self.core_registry {
+			return self.run();
+		}
+
+		loop {
+			let state = match self.fast_step() {
+				Some(state) => {
+					self.has_effect = false;
+					state
+				},
+				None => self.step()?,
+			};
+			match state {
+				State::Running => continue,
+				state => return Ok(state),
+			}
+		}
+	}
+
+	fn fast_step(&mut self) -> Option<State> {
+		if let Some(budget) = self.budget {
+			if self.executed >= budget {
+				return None;
+			}
+		}
+
+		let bound = min(self.memory.len(), self.memory.limit());
+		let pos = self.pos;
+		if pos >= bound {
+			return None;
+		}
+		let cells = &mut self.memory.dense_mut()?[..bound];
+		let base = self.relative_base;
+		let decoded = self.decode_cache.get(pos, cells[pos])?;
+		let modes = decoded.modes;
+
+		match decoded.op {
+			OpInstruction::Add => {
+				let value = self.arithmetic.add(
+					fast_read(cells, base, pos+1, modes[0])?,
+					fast_read(cells, base, pos+2, modes[1])?,
+				)?;
+				cells[fast_addr(cells, base, pos+3, modes[2])?] = value;
+				self.pos += 4;
+			}
+			OpInstruction::Multiply => {
+				let value = self.arithmetic.mul(
+					fast_read(cells, base, pos+1, modes[0])?,
+					fast_read(cells, base, pos+2, modes[1])?,
+				)?;
+				cells[fast_addr(cells, base, pos+3, modes[2])?] = value;
+				self.pos += 4;
+			}
+			OpInstruction::Input => {
+				let addr = fast_addr(cells, base, pos+1, modes[0])?;
+				cells[addr] = self.inputs.pop_front()?;
+				self.pos += 2;
+			}
+			OpInstruction::Output => {
+				let value = fast_read(cells, base, pos+1, modes[0])?;
+				self.pos += 2;
+				self.executed += 1;
+				return Some(State::Produced(value));
+			}
+			OpInstruction::Jump(trigger) => {
+				if trigger == (0 != fast_read(cells, base, pos+1, modes[0])?) {
+					let target = fast_read(cells, base, pos+2, modes[1])?;
+					self.pos = usize::try_from(target).ok()
+						.filter(|&t| t < bound)?;
+				} else {
+					self.pos += 3;
+				}
+			}
+			OpInstruction::Compare(trigger) => {
+				let value = (trigger == fast_read(cells, base, pos+1, modes[0])?
+					.cmp(&fast_read(cells, base, pos+2, modes[1])?)) as i64;
+				cells[fast_addr(cells, base, pos+3, modes[2])?] = value;
+				self.pos += 4;
+			}
+			OpInstruction::AdjustBase => {
+				self.relative_base = base.checked_add(
+					fast_read(cells, base, pos+1, modes[0])?
+				)?;
+				self.pos += 2;
+			}
+			OpInstruction::Terminate => return Some(State::Halted),
+		}
+
+		self.executed += 1;
+		Some(State::Running)
+	}
+}
+
+
+// Operand access for `Machine::fast_step`, restricted to the loaded cells;
+// `None` means the slow path has to deal with it.
+fn fast_read(cells: &[i64], base: i64, pos: usize, mode: u8) -> Option<i64> {
+	let raw = *cells.get(pos)?;
+	let addr = match mode {
+		0 => raw,
+		1 => return Some(raw),
+		2 => raw.checked_add(base)?,
+		_ => return None,
+	};
+
+	cells.get(usize::try_from(addr).ok()?).cloned()
+}
+
+fn fast_addr(cells: &[i64], base: i64, pos: usize, mode: u8) -> Option<usize> {
+	let raw = *cells.get(pos)?;
+	let addr = match mode {
+		0 => raw,
+		2 => raw.checked_add(base)?,
+		_ => return None,
+	};
+
+	usize::try_from(addr).ok().filter(|&addr| addr < cells.len())
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{Machine, State};
+	use super::super::bigint::BigInt;
+	use super::super::error::ErrorKind;
+
+	fn run(program: &[i64]) -> Result<State, ErrorKind> {
+		Machine::new(program.to_vec()).run().map_err(|error| error.kind)
+	}
+
+	#[test]
+	fn only_falling_off_the_end_halts() {
+		assert_eq!(run(&[1101, 1, 1, 3]), Ok(State::Halted));
+		assert_eq!(run(&[1105, 1, 1000]), Err(ErrorKind::BadJumpTarget(1000)));
+		assert_eq!(run(&[1105, 1, 3]), Err(ErrorKind::BadJumpTarget(3)));
+		// written cells can be jumped to, even past the loaded image
+		assert_eq!(run(&[1101, 0, 99, 10, 1105, 1, 10]), Ok(State::Halted));
+
+		let mut machine = Machine::new(vec![1105, 1, 1000]);
+		assert!(machine.run_fast().is_err());
+		assert_eq!(machine.position(), 0);
+	}
+
+	#[test]
+	fn out_of_range_addresses_are_told_apart() {
+		let out_of_bounds = run(&[1, 20_000_000, 0, 0, 99]).unwrap_err();
+		assert_eq!(out_of_bounds, ErrorKind::OutOfBounds(20_000_000));
+
+		let huge: BigInt = "1180591620717411303424".parse().unwrap();
+		let mut machine = Machine::new(vec![
+			BigInt::from(1), huge.clone(), BigInt::from(0), BigInt::from(0), BigInt::from(99),
+		]);
+		let too_large = machine.run().unwrap_err().kind;
+		assert_eq!(too_large, ErrorKind::AddressTooLarge(huge));
+		assert_ne!(out_of_bounds.to_string(), too_large.to_string());
+	}
+
+	fn outputs(program: &[i64]) -> Vec<i64> {
+		let mut outputs = Vec::new();
+		let state = Machine::new(program.to_vec())
+			.run_with(&mut Vec::<i64>::new().into_iter(), &mut outputs);
+		assert_eq!(state, Ok(State::Halted));
+		outputs
+	}
+
+	#[test]
+	fn day9_examples() {
+		let quine = [109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99];
+		assert_eq!(outputs(&quine), quine);
+		assert_eq!(outputs(&[1102, 34915192, 34915192, 7, 4, 7, 99, 0]), [1219070632396864]);
+		assert_eq!(outputs(&[104, 1125899906842624, 99]), [1125899906842624]);
+	}
+
+	#[test]
+	fn failed_steps_leave_the_machine_untouched() {
+		// (program, failing position, error, relative base when it fails)
+		let cases = [
+			(vec![1, -1, 0, 0, 99], 0, ErrorKind::NegativeAddress(-1), 0),
+			(vec![11101, 1, 1, 5, 99], 0, ErrorKind::ImmediateWrite{param: 2}, 0),
+			(vec![301, 0, 0, 0, 99], 0, ErrorKind::InvalidParamMode{param: 0, mode: 3}, 0),
+			(vec![109, i64::MAX, 109, 1, 99], 2, ErrorKind::Overflow, i64::MAX),
+		];
+		for (program, pos, kind, relative_base) in cases.iter() {
+			let mut machine = Machine::new(program.clone());
+			let error = machine.run().unwrap_err();
+			assert_eq!((error.pos, error.word, &error.kind), (*pos, program[*pos], kind));
+
+			assert_eq!(machine.position(), *pos);
+			assert_eq!(machine.relative_base(), *relative_base);
+			assert_eq!(machine.memory().len(), program.len());
+			assert_eq!(machine.memory().window(0, program.len()), *program);
+		}
+	}
+
+	// adds one to the cell at 7 forever
+	const COUNTER: [i64; 8] = [1001, 7, 1, 7, 1105, 1, 0, 0];
+
+	#[test]
+	fn detects_infinite_loops() {
+		let mut machine = Machine::new(vec![1105, 1, 0]);
+		machine.set_loop_detection(true);
+		assert_eq!(
+			machine.run().map_err(|error| error.kind),
+			Err(ErrorKind::InfiniteLoop{period: 1})
+		);
+	}
+
+	#[test]
+	fn counting_is_not_a_loop() {
+		let mut machine = Machine::new(COUNTER.to_vec());
+		machine.set_loop_detection(true);
+		machine.set_instruction_budget(Some(1000));
+		assert_eq!(
+			machine.run().map_err(|error| error.kind),
+			Err(ErrorKind::BudgetExhausted(1000))
+		);
+		assert_eq!(machine.instructions_executed(), 1000);
+	}
+
+	#[test]
+	fn unstep_resets_loop_detection() {
+		let mut machine = Machine::new(COUNTER.to_vec());
+		machine.set_loop_detection(true);
+		let mut effects = Vec::new();
+		for _ in 0..40 {
+			assert_eq!(machine.step(), Ok(State::Running));
+			effects.push(machine.last_effect().unwrap().clone());
+		}
+
+		// the states saved along the way come around again after rewinding
+		for effect in effects.iter().rev().take(20) {
+			machine.unstep(effect);
+		}
+		for _ in 0..40 {
+			assert_eq!(machine.step(), Ok(State::Running));
+		}
+		assert_eq!(machine.memory().peek(7), 30);
+	}
+
+	#[test]
+	fn unstep_undoes_a_halt() {
+		let mut machine = Machine::new(vec![1101, 2, 3, 5, 99, 0]);
+		assert_eq!(machine.step(), Ok(State::Running));
+		let add = machine.last_effect().unwrap().clone();
+		assert_eq!(machine.step(), Ok(State::Halted));
+		let halt = machine.last_effect().unwrap().clone();
+		assert!(add.counted && !halt.counted);
+
+		machine.unstep(&halt);
+		assert_eq!(machine.position(), 4);
+		assert_eq!(machine.instructions_executed(), 1);
+		machine.unstep(&add);
+		assert_eq!(machine.position(), 0);
+		assert_eq!(machine.instructions_executed(), 0);
+		assert_eq!(machine.memory().peek(5), 0);
+	}
+}
@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::vec::Vec;
+
+use super::cell::Cell;
+use super::error::ErrorKind;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+	// one contiguous vector; fastest, but a far write allocates everything
+	// below it
+	Dense,
+	// fixed-size pages allocated on first write
+	Sparse,
+}
+
+// Page indices are small integers, so SipHash is wasted effort on them.
+#[derive(Debug, Clone, Copy, Default)]
+struct PageHasher(u64);
+
+impl Hasher for PageHasher {
+	fn finish(&self) -> u64 {
+		self.0
+	}
+
+	fn write(&mut self, bytes: &[u8]) {
+		for &byte in bytes {
+			self.0 = (self.0 << 8 | u64::from(byte)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+		}
+	}
+
+	fn write_usize(&mut self, value: usize) {
+		self.0 = (value as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+	}
+}
+
+type Pages<C> = HashMap<usize, Box<[C]>, BuildHasherDefault<PageHasher>>;
+
+#[derive(Debug, Clone)]
+enum Cells<C> {
+	Dense(Vec<C>),
+	Sparse{pages: Pages<C>, len: usize},
+}
+
+
+// Program memory. Both backends behave the same: addresses past the end
+// read as zero, writing past the end extends it, and anything at or above
+// the limit is out of bounds.
+#[derive(Debug, Clone)]
+pub struct Memory<C = i64> {
+	cells: Cells<C>,
+	limit: usize,
+}
+
+impl Memory {
+	// enough for every published puzzle; raise it with `set_limit` if needed
+	pub const DEFAULT_LIMIT: usize = 1 << 24;
+	pub const PAGE_SIZE: usize = 1024;
+}
+
+fn empty_page<C: Cell>() -> Box<[C]> {
+	vec![C::default(); Memory::PAGE_SIZE].into_boxed_slice()
+}
+
+impl<C: Cell> Memory<C> {
+	pub fn new(cells: Vec<C>) -> Self {
+		Self{cells: Cells::Dense(cells), limit: Memory::DEFAULT_LIMIT}
+	}
+
+	pub fn sparse(cells: Vec<C>) -> Self {
+		let len = cells.len();
+		let pages = cells.chunks(Memory::PAGE_SIZE).enumerate()
+			.filter(|(_, chunk)| chunk.iter().any(|value| !value.is_zero()))
+			.map(|(index, chunk)| {
+				let mut page = empty_page::<C>();
+				page[..chunk.len()].clone_from_slice(chunk);
+				(index, page)
+			})
+			.collect();
+
+		Self{cells: Cells::Sparse{pages, len}, limit: Memory::DEFAULT_LIMIT}
+	}
+
+	pub fn with_kind(cells: Vec<C>, kind: MemoryKind) -> Self {
+		match kind {
+			MemoryKind::Dense => Self::new(cells),
+			MemoryKind::Sparse => Self::sparse(cells),
+		}
+	}
+
+	pub fn kind(&self) -> MemoryKind {
+		match self.cells {
+			Cells::Dense(_) => MemoryKind::Dense,
+			Cells::Sparse{..} => MemoryKind::Sparse,
+		}
+	}
+
+	pub fn limit(&self) -> usize {
+		self.limit
+	}
+
+	pub fn set_limit(&mut self, limit: usize) {
+		self.limit = limit;
+	}
+
+	// One past the highest address loaded or written.
+	pub fn len(&self) -> usize {
+		match &self.cells {
+			Cells::Dense(cells) => cells.len(),
+			Cells::Sparse{len, ..} => *len,
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// The cells as one vector, if that's how they are stored.
+	pub fn dense(&self) -> Option<&Vec<C>> {
+		match &self.cells {
+			Cells::Dense(cells) => Some(cells),
+			Cells::Sparse{..} => None,
+		}
+	}
+
+	pub fn dense_mut(&mut self) -> Option<&mut Vec<C>> {
+		match &mut self.cells {
+			Cells::Dense(cells) => Some(cells),
+			Cells::Sparse{..} => None,
+		}
+	}
+
+	// Copies out all `len()` cells; for sparse memory that may be a lot.
+	pub fn to_vec(&self) -> Vec<C> {
+		match &self.cells {
+			Cells::Dense(cells) => cells.clone(),
+			Cells::Sparse{pages, len} => {
+				let mut cells = vec![C::default(); *len];
+				for (&index, page) in pages.iter() {
+					let start = index * Memory::PAGE_SIZE;
+					let end = (start + Memory::PAGE_SIZE).min(*len);
+					cells[start..end].clone_from_slice(&page[..end-start]);
+				}
+				cells
+			},
+		}
+	}
+
+	pub fn into_vec(self) -> Vec<C> {
+		match self.cells {
+			Cells::Dense(cells) => cells,
+			Cells::Sparse{..} => self.to_vec(),
+		}
+	}
+
+	// The stretches of non-zero cells as (address, cells), in address
+	// order; together with `len()` they are the whole of memory.
+	pub fn runs(&self) -> Vec<(usize, Vec<C>)> {
+		let mut runs = Vec::<(usize, Vec<C>)>::new();
+		let mut push = |addr: usize, value: &C| {
+			if value.is_zero() {
+				return;
+			}
+			match runs.last_mut() {
+				Some((start, cells)) if *start + cells.len() == addr => cells.push(value.clone()),
+				_ => runs.push((addr, vec![value.clone()])),
+			}
+		};
+
+		match &self.cells {
+			Cells::Dense(cells) => {
+				for (addr, value) in cells.iter().enumerate() {
+					push(addr, value);
+				}
+			},
+			Cells::Sparse{pages, ..} => {
+				let mut indices = pages.keys().cloned().collect::<Vec<_>>();
+				indices.sort_unstable();
+				for index in indices {
+					for (offset, value) in pages[&index].iter().enumerate() {
+						push(index * Memory::PAGE_SIZE + offset, value);
+					}
+				}
+			},
+		}
+		runs
+	}
+
+	// The inverse of `runs`: `len` cells, zero outside the runs. Cells at or
+	// past `len` are dropped.
+	pub fn from_runs(len: usize, runs: &[(usize, Vec<C>)], kind: MemoryKind) -> Self {
+		let cells = match kind {
+			MemoryKind::Dense => Cells::Dense(vec![C::default(); len]),
+			MemoryKind::Sparse => Cells::Sparse{pages: Pages::default(), len},
+		};
+		let mut memory = Self{cells, limit: usize::MAX};
+
+		for (start, values) in runs.iter() {
+			for (offset, value) in values.iter().enumerate() {
+				let addr = start.saturating_add(offset);
+				if addr >= len {
+					break;
+				}
+				if let Ok(cell) = memory.get_mut(addr) {
+					*cell = value.clone();
+				}
+			}
+		}
+
+		memory.limit = Memory::DEFAULT_LIMIT;
+		memory
+	}
+
+	// Up to `count` cells from `addr`, stopping at the end of memory.
+	pub fn window(&self, addr: usize, count: usize) -> Vec<C> {
+		let end = addr.saturating_add(count).min(self.len());
+		(addr..end).map(|addr| self.peek(addr)).collect()
+	}
+
+	// The value at `addr` regardless of the limit.
+	#[inline]
+	pub fn peek(&self, addr: usize) -> C {
+		match &self.cells {
+			Cells::Dense(cells) => cells.get(addr).cloned().unwrap_or_default(),
+			Cells::Sparse{pages, ..} => pages.get(&(addr / Memory::PAGE_SIZE))
+				.map_or_else(C::default, |page| page[addr % Memory::PAGE_SIZE].clone()),
+		}
+	}
+
+	#[inline]
+	pub fn read(&self, addr: usize) -> Result<C, ErrorKind<C>> {
+		if addr >= self.limit {
+			return Err(ErrorKind::OutOfBounds(addr));
+		}
+		// memory beyond the loaded image reads as zero
+		Ok(self.peek(addr))
+	}
+
+	#[inline]
+	pub fn get_mut(&mut self, addr: usize) -> Result<&mut C, ErrorKind<C>> {
+		if addr >= self.limit {
+			return Err(ErrorKind::OutOfBounds(addr));
+		}
+
+		match &mut self.cells {
+			Cells::Dense(cells) => {
+				if addr >= cells.len() {
+					cells.resize(addr+1, C::default());
+				}
+				Ok(&mut cells[addr])
+			},
+			Cells::Sparse{pages, len} => {
+				*len = (*len).max(addr+1);
+				let page = pages.entry(addr / Memory::PAGE_SIZE).or_insert_with(empty_page);
+				Ok(&mut page[addr % Memory::PAGE_SIZE])
+			},
+		}
+	}
+
+	// Shrinks memory back to `len` cells; does nothing if it is smaller.
+	pub fn truncate(&mut self, new_len: usize) {
+		match &mut self.cells {
+			Cells::Dense(cells) => cells.truncate(new_len),
+			Cells::Sparse{pages, len} => {
+				if new_len >= *len {
+					return;
+				}
+				pages.retain(|&index, _| index * Memory::PAGE_SIZE < new_len);
+				if let Some(page) = pages.get_mut(&(new_len / Memory::PAGE_SIZE)) {
+					for cell in page[new_len % Memory::PAGE_SIZE..].iter_mut() {
+						*cell = C::default();
+					}
+				}
+				*len = new_len;
+			},
+		}
+	}
+}
+
+// Equal when every cell is, whichever way each side stores them.
+impl<C: Cell> PartialEq for Memory<C> {
+	fn eq(&self, other: &Memory<C>) -> bool {
+		if self.limit != other.limit || self.len() != other.len() {
+			return false;
+		}
+
+		match (&self.cells, &other.cells) {
+			(Cells::Dense(a), Cells::Dense(b)) => a == b,
+			(Cells::Sparse{pages: a, ..}, Cells::Sparse{pages: b, ..}) => {
+				let zeros = empty_page::<C>();
+				a.keys().chain(b.keys()).all(|index| {
+					a.get(index).map_or(&zeros[..], |page| &page[..])
+						== b.get(index).map_or(&zeros[..], |page| &page[..])
+				})
+			},
+			_ => (0..self.len()).all(|addr| self.peek(addr) == other.peek(addr)),
+		}
+	}
+}
+
+impl<C: Cell> Eq for Memory<C> {}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{Memory, MemoryKind};
+	use super::super::error::ErrorKind;
+
+	const PAGE: usize = Memory::PAGE_SIZE;
+
+	// The same memory in both backends.
+	fn pair(cells: Vec<i64>) -> (Memory, Memory) {
+		(Memory::new(cells.clone()), Memory::sparse(cells))
+	}
+
+	fn write(memory: &mut Memory, addr: usize, value: i64) {
+		*memory.get_mut(addr).unwrap() = value;
+	}
+
+	fn assert_same(dense: &Memory, sparse: &Memory) {
+		assert_eq!((dense.kind(), sparse.kind()), (MemoryKind::Dense, MemoryKind::Sparse));
+		assert_eq!(dense.len(), sparse.len());
+		assert_eq!(dense.to_vec(), sparse.to_vec());
+		assert_eq!(dense.runs(), sparse.runs());
+		assert_eq!(dense, sparse);
+		assert_eq!(sparse, dense);
+	}
+
+	#[test]
+	fn writes_across_pages() {
+		let (mut dense, mut sparse) = pair(vec![1, 0, 2]);
+		assert_same(&dense, &sparse);
+
+		for &(addr, value) in [(PAGE-1, 3), (PAGE, 4), (3*PAGE+5, 5), (2*PAGE-1, 0)].iter() {
+			write(&mut dense, addr, value);
+			write(&mut sparse, addr, value);
+			assert_same(&dense, &sparse);
+		}
+		assert_eq!(dense.len(), 3*PAGE+6);
+		assert_eq!(sparse.window(PAGE-2, 4), [0, 3, 4, 0]);
+		assert_eq!(sparse.runs(), [(0, vec![1]), (2, vec![2]), (PAGE-1, vec![3, 4]), (3*PAGE+5, vec![5])]);
+		assert_eq!(sparse.peek(10*PAGE), 0);
+
+		let mut other = Memory::sparse(Vec::new());
+		write(&mut other, 3*PAGE+5, 6);
+		assert!(other != sparse);
+	}
+
+	#[test]
+	fn truncate() {
+		let cells = (1..=3*PAGE as i64).collect::<Vec<_>>();
+		for &len in [3*PAGE+10, 3*PAGE, 2*PAGE+1, PAGE, PAGE-1, 5, 0].iter() {
+			let (mut dense, mut sparse) = pair(cells.clone());
+			dense.truncate(len);
+			sparse.truncate(len);
+			assert_same(&dense, &sparse);
+			assert_eq!(sparse.len(), len.min(3*PAGE));
+
+			// truncated cells read as zero once memory grows back over them
+			write(&mut dense, 4*PAGE, 7);
+			write(&mut sparse, 4*PAGE, 7);
+			assert_same(&dense, &sparse);
+			assert_eq!((dense.peek(len), sparse.peek(len)), (0, 0));
+		}
+	}
+
+	#[test]
+	fn equality_ignores_representation() {
+		// a sparse page of zeros equals one that was never allocated
+		let (dense, mut sparse) = pair(vec![5; 3]);
+		write(&mut sparse, PAGE+1, 0);
+		write(&mut sparse, PAGE+1, 0);
+		let mut grown = dense.clone();
+		write(&mut grown, PAGE+1, 0);
+		assert_same(&grown, &sparse);
+		assert!(dense != sparse);
+
+		let mut limited = grown.clone();
+		limited.set_limit(PAGE+2);
+		assert!(limited != sparse);
+		sparse.set_limit(PAGE+2);
+		assert!(limited == sparse);
+		assert_eq!(sparse.get_mut(PAGE+2).err(), Some(ErrorKind::OutOfBounds(PAGE+2)));
+		assert_eq!(limited.read(PAGE+2), Err(ErrorKind::OutOfBounds(PAGE+2)));
+	}
+
+	#[test]
+	fn from_runs() {
+		let (mut dense, mut sparse) = pair(vec![1, 2, 0, 3]);
+		write(&mut dense, 2*PAGE, 9);
+		write(&mut sparse, 2*PAGE, 9);
+		write(&mut dense, 2*PAGE+3, 0);
+		write(&mut sparse, 2*PAGE+3, 0);
+
+		for &kind in [MemoryKind::Dense, MemoryKind::Sparse].iter() {
+			let rebuilt = Memory::from_runs(dense.len(), &sparse.runs(), kind);
+			assert_eq!(rebuilt.kind(), kind);
+			assert!(rebuilt == dense && rebuilt == sparse);
+		}
+		// runs past the length are dropped
+		let memory = Memory::from_runs(3, &[(1, vec![4, 5, 6])], MemoryKind::Sparse);
+		assert_eq!(memory.to_vec(), [0, 4, 5]);
+	}
+}
@@ -0,0 +1,65 @@
+// Shared Intcode interpreter.
+//
+// The day binaries pull this in with
+// `#[path = "../intcode/mod.rs"] mod intcode;`. Its tests build with
+// `rustc --test intcode/mod.rs -o intcode-tests` from the repository root.
+
+mod digits;
+mod error;
+mod cell;
+mod memory;
+mod opcode;
+mod params;
+mod machine;
+mod registry;
+mod io;
+mod parse;
+mod disasm;
+mod asm;
+mod debugger;
+mod trace;
+mod snapshot;
+mod reverse;
+mod fast;
+mod cfg;
+mod symbolic;
+mod search;
+mod amplifier;
+mod network;
+mod ascii;
+mod grid;
+mod bigint;
+
+pub use self::digits::Digits;
+pub use self::opcode::OpInstruction;
+pub use self::error::{Error, ErrorKind};
+pub use self::cell::Cell;
+pub use self::memory::{Memory, MemoryKind};
+pub use self::params::{ParameterRef, ParameterMutRef};
+pub use self::machine::{Machine, State, Effect, Write, Exec, Arithmetic};
+pub use self::registry::{Registry, OpSpec, Flow, Semantics};
+pub use self::io::{
+	InputSource, OutputSink,
+	IterInput, ReaderInput, WriterOutput, Terminal,
+};
+pub use self::parse::{
+	LoadError,
+	parse_words, parse_program, read_words, load_words, load_program,
+};
+pub use self::disasm::{
+	Operand, Instruction, Listing, Line,
+	decode, disassemble, print_listing,
+};
+pub use self::asm::{AsmError, AsmErrorKind, assemble, to_code_string};
+pub use self::debugger::{Debugger, Event};
+pub use self::trace::{Tracer, TraceFormat, read_binary};
+pub use self::snapshot::Snapshot;
+pub use self::reverse::UndoLog;
+pub use self::cfg::{Cfg, Block, Edge, EdgeKind, Terminator};
+pub use self::symbolic::{Poly, SymbolicError, symbolic_result, solve_noun_verb};
+pub use self::search::{Search, SearchError, Variable, Outcome};
+pub use self::amplifier::{Amplifiers, AmpError, Wiring};
+pub use self::network::{Network, NetError, NetEvent, Packet, NAT_ADDRESS};
+pub use self::ascii::Ascii;
+pub use self::grid::{Coordinate, Grid, PaintRobot, TileScreen, CameraFrame};
+pub use self::bigint::{BigInt, ParseBigIntError};
@@ -0,0 +1,402 @@
+// Many machines running one program cooperatively, exchanging packets.
+//
+// Each machine is given its network address as its first input. Outputs are
+// read in triples `(dest, x, y)` and queued for the destination, which reads
+// them back as `x` then `y`; a machine asking for input with nothing queued
+// gets -1 and gives up its turn. Packets sent to the NAT address are held by
+// the NAT, which sends the last of them to address 0 whenever the whole
+// network goes idle.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::vec::Vec;
+
+use super::error::Error;
+use super::machine::{Machine, State};
+
+
+pub const NAT_ADDRESS: i64 = 255;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+	pub source: i64,
+	pub dest: i64,
+	pub x: i64,
+	pub y: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetEvent {
+	Delivered(Packet),
+	ToNat(Packet),
+	// the network was idle and the NAT resent its packet to address 0
+	NatWake(Packet),
+	// sent to an address that nobody has; the packet is dropped
+	Undeliverable(Packet),
+	// the network was idle and the NAT had nothing to send
+	Idle,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+	// the machine at this address failed
+	Machine(usize, Error),
+	// idle with no NAT packet, or every machine halted
+	Stalled,
+}
+
+impl fmt::Display for NetError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Machine(addr, error) => write!(f, "machine {}: {}", addr, error),
+			Self::Stalled => write!(f, "network stalled"),
+		}
+	}
+}
+
+impl std::error::Error for NetError {}
+
+
+#[derive(Debug, Clone)]
+struct Node {
+	machine: Machine,
+	queue: VecDeque<(i64, i64)>,
+	// output words of a packet still being sent
+	partial: Vec<i64>,
+	// ended its last turn asking for input with an empty queue
+	idle: bool,
+	halted: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Network {
+	nodes: Vec<Node>,
+	nat: Option<Packet>,
+	// instructions a machine may run per turn, so one busy machine can't
+	// starve the rest
+	slice: u64,
+	rounds: u64,
+}
+
+impl Network {
+	const DEFAULT_SLICE: u64 = 10_000;
+
+	pub fn new(program: Vec<i64>, size: usize) -> Self {
+		Self::from_machine(Machine::new(program), size)
+	}
+
+	// Nodes that keep the memory backend, limits and other settings of
+	// `machine`, which should not have started running.
+	pub fn from_machine(machine: Machine, size: usize) -> Self {
+		let nodes = (0..size).map(|addr| {
+			let mut machine = machine.clone();
+			machine.push_input(addr as i64);
+			Node{
+				machine,
+				queue: VecDeque::new(),
+				partial: Vec::new(),
+				idle: false,
+				halted: false,
+			}
+		}).collect();
+
+		Self{nodes, nat: None, slice: Self::DEFAULT_SLICE, rounds: 0}
+	}
+
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn machine(&self, addr: usize) -> &Machine {
+		&self.nodes[addr].machine
+	}
+
+	pub fn machine_mut(&mut self, addr: usize) -> &mut Machine {
+		&mut self.nodes[addr].machine
+	}
+
+	pub fn queue(&self, addr: usize) -> &VecDeque<(i64, i64)> {
+		&self.nodes[addr].queue
+	}
+
+	pub fn nat_packet(&self) -> Option<Packet> {
+		self.nat
+	}
+
+	pub fn rounds(&self) -> u64 {
+		self.rounds
+	}
+
+	pub fn set_slice(&mut self, slice: u64) {
+		self.slice = slice.max(1);
+	}
+
+	// Applies to each machine separately, so that a program that never
+	// idles or halts fails instead of running forever.
+	pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+		for node in self.nodes.iter_mut() {
+			node.machine.set_instruction_budget(budget);
+		}
+	}
+
+	pub fn send(&mut self, packet: Packet) -> NetEvent {
+		if packet.dest == NAT_ADDRESS {
+			self.nat = Some(packet);
+			return NetEvent::ToNat(packet);
+		}
+
+		match self.nodes.get_mut(packet.dest as usize).filter(|_| packet.dest >= 0) {
+			Some(node) => {
+				node.queue.push_back((packet.x, packet.y));
+				NetEvent::Delivered(packet)
+			},
+			None => NetEvent::Undeliverable(packet),
+		}
+	}
+
+	fn is_idle(&self) -> bool {
+		self.nodes.iter().all(|node| {
+			node.queue.is_empty() && node.partial.is_empty() && (node.idle || node.halted)
+		})
+	}
+
+	// Gives every machine one turn in address order, then lets the NAT act
+	// if the network went idle. Returns what happened, in order.
+	pub fn round(&mut self) -> Result<Vec<NetEvent>, NetError> {
+		let mut events = Vec::<NetEvent>::new();
+
+		for addr in 0..self.nodes.len() {
+			let mut waiting = false;
+			for _ in 0..self.slice {
+				let node = &mut self.nodes[addr];
+				if node.halted {
+					break;
+				}
+
+				match node.machine.step().map_err(|error| NetError::Machine(addr, error))? {
+					State::Running => {},
+					State::Halted | State::Exited(_) => node.halted = true,
+					State::Produced(value) => {
+						node.idle = false;
+						node.partial.push(value);
+						if node.partial.len() == 3 {
+							let packet = Packet{
+								source: addr as i64,
+								dest: node.partial[0],
+								x: node.partial[1],
+								y: node.partial[2],
+							};
+							node.partial.clear();
+							events.push(self.send(packet));
+						}
+					},
+					State::NeedsInput => match node.queue.pop_front() {
+						Some((x, y)) => {
+							node.idle = false;
+							node.machine.push_input(x);
+							node.machine.push_input(y);
+						},
+						None => {
+							node.idle = true;
+							waiting = true;
+							node.machine.push_input(-1);
+							break;
+						},
+					},
+				};
+			}
+			// it took the -1 it was given and is still busy with it
+			if !waiting {
+				self.nodes[addr].idle = false;
+			}
+		}
+		self.rounds += 1;
+
+		if self.is_idle() {
+			events.push(match self.nat {
+				Some(packet) => {
+					let packet = Packet{source: NAT_ADDRESS, dest: 0, ..packet};
+					self.send(packet);
+					NetEvent::NatWake(packet)
+				},
+				None => NetEvent::Idle,
+			});
+		}
+
+		Ok(events)
+	}
+
+	// Runs rounds until `stop` accepts an event, and returns that event.
+	pub fn run_until<F>(&mut self, mut stop: F) -> Result<NetEvent, NetError>
+	where
+		F: FnMut(&NetEvent) -> bool,
+	{
+		loop {
+			for event in self.round()? {
+				if stop(&event) {
+					return Ok(event);
+				}
+				if event == NetEvent::Idle {
+					return Err(NetError::Stalled);
+				}
+			}
+			if self.nodes.iter().all(|node| node.halted) {
+				return Err(NetError::Stalled);
+			}
+		}
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{NetError, NetEvent, Network, Packet, NAT_ADDRESS};
+	use super::super::asm::assemble;
+	use super::super::error::ErrorKind;
+
+	// Node 0 sends (7, 8) to node 1, which passes (7, 9) on to the NAT;
+	// everyone else just listens.
+	fn cluster(size: usize) -> Network {
+		let program = assemble("
+				in [addr]
+				jnz [addr], #listen
+				out #1
+				out #7
+				out #8
+		listen:	in [x]
+				eq [x], #-1, [t]
+				jnz [t], #listen
+				in [y]
+				eq [addr], #1, [t]
+				jz [t], #listen
+				add [y], #1, [y]
+				out #255
+				out [x]
+				out [y]
+				jz #0, #listen
+		addr:	data 0
+		x:		data 0
+		y:		data 0
+		t:		data 0
+		").unwrap();
+		Network::new(program, size)
+	}
+
+	fn packet(source: i64, dest: i64, x: i64, y: i64) -> Packet {
+		Packet{source, dest, x, y}
+	}
+
+	#[test]
+	fn routes_through_the_nat() {
+		let mut network = cluster(3);
+		let wake = NetEvent::NatWake(packet(NAT_ADDRESS, 0, 7, 9));
+		assert_eq!(network.round(), Ok(vec![
+			NetEvent::Delivered(packet(0, 1, 7, 8)),
+			NetEvent::ToNat(packet(1, NAT_ADDRESS, 7, 9)),
+			wake,
+		]));
+		assert_eq!(network.nat_packet(), Some(packet(1, NAT_ADDRESS, 7, 9)));
+		assert_eq!(network.queue(0).iter().cloned().collect::<Vec<_>>(), [(7, 9)]);
+		// listening with nothing queued reads -1
+		assert_eq!(network.machine(2).pending_inputs().iter().cloned().collect::<Vec<_>>(), [-1]);
+
+		// node 0 takes the packet, the network goes idle again and the NAT
+		// sends it once more
+		assert_eq!(network.round(), Ok(vec![wake]));
+		assert_eq!(network.queue(0).len(), 1);
+		assert_eq!(network.rounds(), 2);
+	}
+
+	#[test]
+	fn run_until_reports_events_in_order() {
+		let mut network = cluster(3);
+		let mut seen = Vec::new();
+		let stop = network.run_until(|event| {
+			seen.push(*event);
+			matches!(event, NetEvent::NatWake(_))
+		});
+		assert_eq!(stop, Ok(NetEvent::NatWake(packet(NAT_ADDRESS, 0, 7, 9))));
+		assert_eq!(seen, [
+			NetEvent::Delivered(packet(0, 1, 7, 8)),
+			NetEvent::ToNat(packet(1, NAT_ADDRESS, 7, 9)),
+			NetEvent::NatWake(packet(NAT_ADDRESS, 0, 7, 9)),
+		]);
+	}
+
+	#[test]
+	fn stalls_without_a_nat_packet() {
+		// node 1 doesn't exist, so nothing ever reaches the NAT
+		let mut network = cluster(1);
+		assert_eq!(network.round(), Ok(vec![
+			NetEvent::Undeliverable(packet(0, 1, 7, 8)),
+			NetEvent::Idle,
+		]));
+
+		let mut network = cluster(1);
+		assert_eq!(
+			network.run_until(|event| matches!(event, NetEvent::ToNat(_))),
+			Err(NetError::Stalled)
+		);
+	}
+
+	#[test]
+	fn busy_machines_use_up_their_budget() {
+		// reads its address, then loops forever without asking for more
+		let mut network = Network::new(vec![3, 5, 1105, 1, 2, 0], 2);
+		network.set_instruction_budget(Some(100));
+		match network.run_until(|_| false) {
+			Err(NetError::Machine(0, error)) => {
+				assert_eq!(error.kind, ErrorKind::BudgetExhausted(100));
+			},
+			result => panic!("unexpected {:?}", result),
+		}
+	}
+
+	#[test]
+	fn busy_machines_are_not_idle() {
+		// node 0 sends a packet to the NAT and listens after a short count;
+		// node 1 listens once, then counts for longer than a turn
+		let program = assemble("
+				in [addr]
+				jnz [addr], #one
+				out #255
+				out #1
+				out #2
+				add #0, #0, [n]
+		zero:	add [n], #1, [n]
+				lt [n], #3, [t]
+				jnz [t], #zero
+		listen:	in [x]
+				jz #0, #listen
+		one:	in [x]
+				add #0, #0, [n]
+		ones:	add [n], #1, [n]
+				lt [n], #10, [t]
+				jnz [t], #ones
+				jz #0, #listen
+		addr:	data 0
+		x:		data 0
+		n:		data 0
+		t:		data 0
+		").unwrap();
+		let mut network = Network::new(program, 2);
+		network.set_slice(10);
+
+		assert_eq!(network.round(), Ok(vec![NetEvent::ToNat(packet(0, NAT_ADDRESS, 1, 2))]));
+		// node 0 is listening now, but node 1 is still counting
+		assert_eq!(network.round(), Ok(vec![]));
+		assert_eq!(network.round(), Ok(vec![]));
+		assert_eq!(
+			network.run_until(|event| matches!(event, NetEvent::NatWake(_))),
+			Ok(NetEvent::NatWake(packet(NAT_ADDRESS, 0, 1, 2)))
+		);
+		assert_eq!(network.rounds(), 5);
+	}
+}
@@ -0,0 +1,100 @@
+use std::cmp::Ordering;
+
+use super::digits::Digits;
+use super::error::ErrorKind;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OpInstruction {
+	Add,
+	Multiply,
+	Input,
+	Output,
+	Jump(bool),
+	Compare(Ordering),
+	AdjustBase,
+
+	Terminate,
+}
+
+impl OpInstruction {
+	pub fn from_opcode(opcode: u32) -> Result<OpInstruction, ErrorKind> {
+		match Digits::from(opcode).subdigits(..2).into() {
+			99u32 => Ok(Self::Terminate),
+			1u32 => Ok(Self::Add),
+			2u32 => Ok(Self::Multiply),
+			3u32 => Ok(Self::Input),
+			4u32 => Ok(Self::Output),
+			5u32 => Ok(Self::Jump(true)),
+			6u32 => Ok(Self::Jump(false)),
+			7u32 => Ok(Self::Compare(Ordering::Less)),
+			8u32 => Ok(Self::Compare(Ordering::Equal)),
+			9u32 => Ok(Self::AdjustBase),
+			n => Err(ErrorKind::InvalidOpcode(i64::from(n)))
+		}
+	}
+
+	pub fn opcode(&self) -> u32 {
+		match self {
+			Self::Add => 1,
+			Self::Multiply => 2,
+			Self::Input => 3,
+			Self::Output => 4,
+			Self::Jump(true) => 5,
+			Self::Jump(false) => 6,
+			Self::Compare(Ordering::Less) => 7,
+			Self::Compare(_) => 8,
+			Self::AdjustBase => 9,
+			Self::Terminate => 99,
+		}
+	}
+
+	pub fn param_count(&self) -> u8 {
+		match self {
+			Self::Add | Self::Multiply | Self::Compare(_) => 3,
+			Self::Jump(_) => 2,
+			Self::Input | Self::Output | Self::AdjustBase => 1,
+			Self::Terminate => 0,
+		}
+	}
+
+	// index of the parameter the instruction writes to, if any
+	pub fn write_param(&self) -> Option<u8> {
+		match self {
+			Self::Add | Self::Multiply | Self::Compare(_) => Some(2),
+			Self::Input => Some(0),
+			_ => None,
+		}
+	}
+
+	pub fn mnemonic(&self) -> &'static str {
+		match self {
+			Self::Add => "add",
+			Self::Multiply => "mul",
+			Self::Input => "in",
+			Self::Output => "out",
+			Self::Jump(true) => "jnz",
+			Self::Jump(false) => "jz",
+			Self::Compare(Ordering::Less) => "lt",
+			Self::Compare(_) => "eq",
+			Self::AdjustBase => "arb",
+			Self::Terminate => "hlt",
+		}
+	}
+
+	pub fn from_mnemonic(mnemonic: &str) -> Option<OpInstruction> {
+		match mnemonic {
+			"add" => Some(Self::Add),
+			"mul" => Some(Self::Multiply),
+			"in" => Some(Self::Input),
+			"out" => Some(Self::Output),
+			"jnz" => Some(Self::Jump(true)),
+			"jz" => Some(Self::Jump(false)),
+			"lt" => Some(Self::Compare(Ordering::Less)),
+			"eq" => Some(Self::Compare(Ordering::Equal)),
+			"arb" => Some(Self::AdjustBase),
+			"hlt" => Some(Self::Terminate),
+			_ => None,
+		}
+	}
+}
@@ -0,0 +1,118 @@
+use std::convert::TryFrom;
+
+use super::cell::Cell;
+use super::digits::Digits;
+use super::error::ErrorKind;
+use super::machine::Arithmetic;
+use super::memory::Memory;
+
+
+pub fn address<C: Cell>(value: &C, relative_base: i64) -> Result<usize, ErrorKind<C>> {
+	let value_pos = value.sum(&C::from(relative_base), Arithmetic::Checked).ok_or(
+		ErrorKind::Overflow
+	)?;
+
+	match value_pos.to_i64().map(usize::try_from) {
+		Some(Ok(addr)) => Ok(addr),
+		_ if value_pos < C::default() => Err(ErrorKind::NegativeAddress(value_pos)),
+		_ => Err(ErrorKind::AddressTooLarge(value_pos)),
+	}
+}
+
+
+#[derive(Debug)]
+pub enum ParameterRef {
+	Position(usize),
+	Immediate(usize),
+	Relative(usize),
+}
+
+impl ParameterRef {
+	pub fn from_pos_mode<C>(pos: usize, param: u8, mode: u32)
+	-> Result<ParameterRef, ErrorKind<C>> {
+		match mode {
+			0 => Ok(Self::Position(pos)),
+			1 => Ok(Self::Immediate(pos)),
+			2 => Ok(Self::Relative(pos)),
+			m => Err(ErrorKind::InvalidParamMode{param, mode: m})
+		}
+	}
+	pub fn deref<C: Cell>(&self, memory: &Memory<C>, relative_base: i64)
+	-> Result<C, ErrorKind<C>> {
+		match self {
+			Self::Immediate(pos) => memory.read(*pos),
+			Self::Position(pos) => memory.read(
+				address(&memory.read(*pos)?, 0)?
+			),
+			Self::Relative(pos) => memory.read(
+				address(&memory.read(*pos)?, relative_base)?
+			),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum ParameterMutRef {
+	Position(usize),
+	Relative(usize),
+}
+
+impl ParameterMutRef {
+	pub fn from_pos_mode<C>(pos: usize, param: u8, mode: u32)
+	-> Result<ParameterMutRef, ErrorKind<C>> {
+		match mode {
+			0 => Ok(Self::Position(pos)),
+			1 => Err(ErrorKind::ImmediateWrite{param}),
+			2 => Ok(Self::Relative(pos)),
+			m => Err(ErrorKind::InvalidParamMode{param, mode: m})
+		}
+	}
+	pub fn address<C: Cell>(&self, memory: &Memory<C>, relative_base: i64)
+	-> Result<usize, ErrorKind<C>> {
+		match self {
+			Self::Position(pos) => address(&memory.read(*pos)?, 0),
+			Self::Relative(pos) => address(&memory.read(*pos)?, relative_base),
+		}
+	}
+	pub fn deref<'a, C: Cell>(&self, memory: &'a mut Memory<C>, relative_base: i64)
+	-> Result<&'a mut C, ErrorKind<C>> {
+		let value_pos = self.address(memory, relative_base)?;
+
+		memory.get_mut(value_pos)
+	}
+}
+
+
+fn param_mode<C: Cell>(memory: &Memory<C>, pos: usize, offset: u8)
+-> Result<u32, ErrorKind<C>> {
+	let word = memory.read(pos)?;
+	let modes = Digits::from(word.to_i64().and_then(|w| u32::try_from(w).ok()).ok_or(
+		ErrorKind::InvalidOpcode(word)
+	)?);
+
+	Ok(modes.subdigits(2+offset..3+offset).into())
+}
+
+pub fn get_param_ref<C: Cell>(
+	memory: &Memory<C>, relative_base: i64, pos: usize, offset: u8
+) -> Result<C, ErrorKind<C>> {
+	ParameterRef
+	::from_pos_mode(
+		pos+1+usize::from(offset),
+		offset,
+		param_mode(memory, pos, offset)?
+	)?
+	.deref(memory, relative_base)
+}
+
+pub fn get_param_addr<C: Cell>(
+	memory: &Memory<C>, relative_base: i64, pos: usize, offset: u8
+) -> Result<usize, ErrorKind<C>> {
+	ParameterMutRef
+	::from_pos_mode(
+		pos+1+usize::from(offset),
+		offset,
+		param_mode(memory, pos, offset)?
+	)?
+	.address(memory, relative_base)
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+use std::vec::Vec;
+
+
+#[derive(Debug)]
+pub enum LoadError {
+	Io(io::Error),
+	// `index` counts the program's words from zero; `line` is one-based
+	BadWord{index: usize, line: usize, text: String},
+	Empty,
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			LoadError::Io(error) => write!(f, "cannot read program: {}", error),
+			LoadError::BadWord{index, line, text} => write!(
+				f, "line {}: word {} ({:?}) is not an integer", line, index, text
+			),
+			LoadError::Empty => write!(f, "program is empty"),
+		}
+	}
+}
+
+impl std::error::Error for LoadError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			LoadError::Io(error) => Some(error),
+			_ => None,
+		}
+	}
+}
+
+impl From<io::Error> for LoadError {
+	fn from(error: io::Error) -> Self {
+		LoadError::Io(error)
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+// A line without its `#` comment or surrounding whitespace.
+fn code_of(line: &str) -> &str {
+	match line.find('#') {
+		Some(end) => line[..end].trim(),
+		None => line.trim(),
+	}
+}
+
+// Comma-separated words, which may span lines; each line may end in a comma
+// and have a `#` comment, and blank lines are ignored.
+pub fn parse_words<T: FromStr>(text: &str) -> Result<Vec<T>, LoadError> {
+	let mut words = Vec::<T>::new();
+
+	for (line, code) in text.lines().map(code_of).enumerate() {
+		let code = code.strip_suffix(',').unwrap_or(code);
+		if code.is_empty() {
+			continue;
+		}
+		for word in code.split(',') {
+			let word = word.trim();
+			words.push(word.parse::<T>().map_err(|_| LoadError::BadWord{
+				index: words.len(),
+				line: line+1,
+				text: word.to_string(),
+			})?);
+		}
+	}
+
+	match words.is_empty() {
+		true => Err(LoadError::Empty),
+		false => Ok(words),
+	}
+}
+
+pub fn parse_program(text: &str) -> Result<Vec<i64>, LoadError> {
+	parse_words(text)
+}
+
+// Reads a program from an interactive stream, leaving whatever follows it
+// unread: the program ends with the first line that has words and doesn't
+// end in a comma.
+pub fn read_words<T: FromStr, R: BufRead>(mut reader: R) -> Result<Vec<T>, LoadError> {
+	let mut text = String::new();
+	loop {
+		let start = text.len();
+		if reader.read_line(&mut text)? == 0 {
+			break;
+		}
+		let code = code_of(&text[start..]);
+		if !code.is_empty() && !code.ends_with(',') {
+			break;
+		}
+	}
+
+	parse_words(&text)
+}
+
+// Loads a program from the file at `path`, or from stdin if there is none.
+// Only stdin stops at the end of the program, since inputs follow it there.
+pub fn load_words<T: FromStr>(path: Option<&str>) -> Result<Vec<T>, LoadError> {
+	match path {
+		Some(path) => parse_words(&fs::read_to_string(path)?),
+		None => {
+			let stdin = io::stdin();
+			read_words(stdin.lock())
+		},
+	}
+}
+
+pub fn load_program(path: Option<&str>) -> Result<Vec<i64>, LoadError> {
+	load_words(path)
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_program, read_words, LoadError};
+
+	fn bad_word(text: &str) -> Option<(usize, usize, String)> {
+		match parse_program(text) {
+			Err(LoadError::BadWord{index, line, text}) => Some((index, line, text)),
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn tolerates_layout() {
+		let text = "# header\n1,0,0,3,\n\n  2, 3, 4, 0 ,  # tail\n99,\n";
+		assert_eq!(parse_program(text).ok(), Some(vec![1, 0, 0, 3, 2, 3, 4, 0, 99]));
+		assert_eq!(parse_program("1,2\r\n3").ok(), Some(vec![1, 2, 3]));
+	}
+
+	#[test]
+	fn reports_bad_words() {
+		assert_eq!(bad_word("1,2,x3,4"), Some((2, 1, "x3".to_string())));
+		assert_eq!(bad_word("1,2,\n3,,4"), Some((3, 2, "".to_string())));
+		assert_eq!(bad_word("1 2"), Some((0, 1, "1 2".to_string())));
+		assert_eq!(bad_word("1,99999999999999999999"), Some((1, 1, "99999999999999999999".to_string())));
+	}
+
+	#[test]
+	fn rejects_empty_programs() {
+		for &text in ["", "\n", "# nothing\n,\n"].iter() {
+			match parse_program(text) {
+				Err(LoadError::Empty) => {},
+				result => panic!("{:?} gave {:?}", text, result),
+			}
+		}
+	}
+
+	#[test]
+	fn reads_up_to_the_end_of_the_program() {
+		let mut input = "# program\n1,2,\n3\n4\n".as_bytes();
+		let program = read_words::<i64, _>(&mut input).ok();
+
+		assert_eq!(program, Some(vec![1, 2, 3]));
+		assert_eq!(input, b"4\n");
+	}
+
+	#[test]
+	fn files_join_every_line() {
+		assert_eq!(parse_program("1,0,0,0\n99\n").ok(), Some(vec![1, 0, 0, 0, 99]));
+
+		// a stream stops there instead, as inputs follow the program
+		let mut input = "1,0,0,0\n99\n".as_bytes();
+		assert_eq!(read_words::<i64, _>(&mut input).ok(), Some(vec![1, 0, 0, 0]));
+		assert_eq!(input, b"99\n");
+	}
+
+	#[test]
+	fn loads_the_day2_input() {
+		let text = include_str!("../day2/edited-input.txt");
+		let expected: Vec<i64> = text.trim().split(',')
+			.map(|word| word.parse::<i64>().unwrap())
+			.collect();
+
+		assert_eq!(parse_program(text).ok(), Some(expected));
+	}
+}
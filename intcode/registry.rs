@@ -0,0 +1,293 @@
+// Instruction set of a machine, keyed by opcode. Each entry declares its
+// operands and supplies its semantics; the interpreter decodes parameter
+// modes, advances the instruction pointer and records effects, so custom
+// instructions only need to say what they compute.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::vec::Vec;
+
+use super::cell::Cell;
+use super::error::ErrorKind;
+use super::machine::Exec;
+use super::opcode::OpInstruction;
+
+
+// What the machine does after an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow<C = i64> {
+	// continue past the instruction and its operands
+	Next,
+	Jump(C),
+	// nothing was executed; retry once input is available
+	NeedsInput,
+	Produced(C),
+	Halt,
+	// halt, handing the caller a value such as an exit code
+	Exit(C),
+}
+
+pub type Semantics<C = i64> =
+	Arc<dyn Fn(&mut Exec<C>) -> Result<Flow<C>, ErrorKind<C>> + Send + Sync>;
+
+
+#[derive(Clone)]
+pub struct OpSpec<C = i64> {
+	pub opcode: u32,
+	pub mnemonic: &'static str,
+	pub arity: u8,
+	// index of the parameter the instruction writes to, if any
+	pub write_param: Option<u8>,
+	// the core instruction this is, for the tools that only know those
+	pub op: Option<OpInstruction>,
+	pub semantics: Semantics<C>,
+}
+
+impl<C> fmt::Debug for OpSpec<C> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("OpSpec")
+			.field("opcode", &self.opcode)
+			.field("mnemonic", &self.mnemonic)
+			.field("arity", &self.arity)
+			.field("write_param", &self.write_param)
+			.field("op", &self.op)
+			.finish()
+	}
+}
+
+impl OpSpec {
+	// Instructions have at most this many parameters, since an opcode word
+	// only has room for that many mode digits.
+	pub const MAX_ARITY: u8 = 3;
+}
+
+impl<C: Cell> OpSpec<C> {
+	pub fn new<F>(opcode: u32, mnemonic: &str, arity: u8, write_param: Option<u8>, semantics: F)
+	-> Self
+	where
+		F: Fn(&mut Exec<C>) -> Result<Flow<C>, ErrorKind<C>> + Send + Sync + 'static,
+	{
+		assert!(opcode < 100, "opcode {} has more than two digits", opcode);
+		assert!(arity <= OpSpec::MAX_ARITY, "too many parameters for opcode {}", opcode);
+		assert!(
+			write_param.is_none_or(|param| param < arity),
+			"write parameter out of range for opcode {}", opcode
+		);
+		assert!(valid_mnemonic(mnemonic), "bad mnemonic {:?} for opcode {}", mnemonic, opcode);
+
+		Self{
+			opcode,
+			mnemonic: intern(mnemonic),
+			arity,
+			write_param,
+			op: None,
+			semantics: Arc::new(semantics),
+		}
+	}
+
+	fn core(op: OpInstruction) -> Self {
+		let mut spec = Self::new(
+			op.opcode(), op.mnemonic(), op.param_count(), op.write_param(),
+			move |exec: &mut Exec<C>| core_semantics(op, exec),
+		);
+		spec.op = Some(op);
+		spec
+	}
+}
+
+// Lowercase letters, digits and underscores, so that mnemonics can go into
+// listings and traces as they are.
+pub fn valid_mnemonic(mnemonic: &str) -> bool {
+	!mnemonic.is_empty() && mnemonic.bytes()
+		.all(|byte| matches!(byte, b'a'..=b'z' | b'0'..=b'9' | b'_'))
+}
+
+// Mnemonics are kept for the life of the program, so that every executed
+// instruction can record its own for free; each distinct one is stored once.
+pub fn intern(mnemonic: &str) -> &'static str {
+	static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+	let mut names = NAMES.get_or_init(|| Mutex::new(HashSet::new()))
+		.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+	match names.get(mnemonic) {
+		Some(name) => name,
+		None => {
+			let name: &'static str = Box::leak(mnemonic.to_string().into_boxed_str());
+			names.insert(name);
+			name
+		},
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+fn core_semantics<C: Cell>(op: OpInstruction, exec: &mut Exec<C>)
+-> Result<Flow<C>, ErrorKind<C>> {
+	match op {
+		OpInstruction::Add => {
+			let value = exec.read(0)?.sum(&exec.read(1)?, exec.arithmetic())
+				.ok_or(ErrorKind::Overflow)?;
+			exec.write(2, value)?;
+		}
+		OpInstruction::Multiply => {
+			let value = exec.read(0)?.product(&exec.read(1)?, exec.arithmetic())
+				.ok_or(ErrorKind::Overflow)?;
+			exec.write(2, value)?;
+		}
+		OpInstruction::Input => {
+			let value = match exec.peek_input() {
+				Some(value) => value.clone(),
+				None => return Ok(Flow::NeedsInput),
+			};
+			exec.write(0, value)?;
+			exec.take_input();
+		}
+		OpInstruction::Output => return Ok(Flow::Produced(exec.read(0)?)),
+		OpInstruction::Jump(trigger) => {
+			if trigger != exec.read(0)?.is_zero() {
+				return Ok(Flow::Jump(exec.read(1)?));
+			}
+		}
+		OpInstruction::Compare(trigger) => {
+			let value = (trigger == exec.read(0)?.cmp(&exec.read(1)?)) as i64;
+			exec.write(2, C::from(value))?;
+		}
+		OpInstruction::AdjustBase => {
+			let base = exec.read(0)?.to_i64()
+				.and_then(|offset| exec.relative_base().checked_add(offset))
+				.ok_or(ErrorKind::Overflow)?;
+			exec.set_relative_base(base);
+		}
+		OpInstruction::Terminate => return Ok(Flow::Halt),
+	}
+
+	Ok(Flow::Next)
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct Registry<C = i64> {
+	specs: Vec<Option<OpSpec<C>>>,
+}
+
+impl<C: Cell> Registry<C> {
+	pub fn empty() -> Self {
+		Self{specs: vec![None; 100]}
+	}
+
+	// The standard instruction set: opcodes 1-9 and 99.
+	pub fn core() -> Self {
+		let mut registry = Self::empty();
+		for &op in [
+			OpInstruction::Add,
+			OpInstruction::Multiply,
+			OpInstruction::Input,
+			OpInstruction::Output,
+			OpInstruction::Jump(true),
+			OpInstruction::Jump(false),
+			OpInstruction::Compare(Ordering::Less),
+			OpInstruction::Compare(Ordering::Equal),
+			OpInstruction::AdjustBase,
+			OpInstruction::Terminate,
+		].iter() {
+			registry.register(OpSpec::core(op));
+		}
+		registry
+	}
+
+	// Shared copy of `core()`, which machines use unless told otherwise.
+	pub fn shared_core() -> Arc<Registry<C>> {
+		C::shared_core()
+	}
+
+	// Adds or replaces the instruction at `spec.opcode`, returning the one
+	// it replaced.
+	pub fn register(&mut self, spec: OpSpec<C>) -> Option<OpSpec<C>> {
+		let opcode = spec.opcode as usize;
+		self.specs[opcode].replace(spec)
+	}
+
+	pub fn unregister(&mut self, opcode: u32) -> Option<OpSpec<C>> {
+		self.specs.get_mut(opcode as usize).and_then(|spec| spec.take())
+	}
+
+	pub fn get(&self, opcode: u32) -> Option<&OpSpec<C>> {
+		self.specs.get(opcode as usize).and_then(|spec| spec.as_ref())
+	}
+
+	pub fn by_mnemonic(&self, mnemonic: &str) -> Option<&OpSpec<C>> {
+		self.iter().find(|spec| spec.mnemonic == mnemonic)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item=&OpSpec<C>> {
+		self.specs.iter().filter_map(|spec| spec.as_ref())
+	}
+}
+
+impl<C: Cell> Default for Registry<C> {
+	fn default() -> Self {
+		Self::core()
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use super::{Flow, OpSpec, Registry};
+	use super::super::error::ErrorKind;
+	use super::super::machine::{Exec, Machine, State};
+
+	fn exit_spec() -> OpSpec {
+		OpSpec::new(50, "exit", 1, None, |exec: &mut Exec| Ok(Flow::Exit(exec.read(0)?)))
+	}
+
+	#[test]
+	fn custom_exit() {
+		let mut registry = Registry::core();
+		assert!(registry.register(exit_spec()).is_none());
+		let registry = Arc::new(registry);
+		assert_eq!(registry.by_mnemonic("exit").map(|spec| spec.arity), Some(1));
+
+		// exits with the sum it computes
+		let program = vec![1101, 2, 3, 6, 50, 6, 0];
+		let mut machine = Machine::with_registry(program.clone(), registry.clone());
+		assert_eq!(machine.run(), Ok(State::Exited(5)));
+		assert_eq!(machine.last_effect().map(|effect| effect.mnemonic), Some("exit"));
+
+		let mut machine = Machine::with_registry(program, registry);
+		assert_eq!(machine.run_fast(), Ok(State::Exited(5)));
+		assert_eq!(machine.instructions_executed(), 1);
+	}
+
+	#[test]
+	fn replaced_core_instructions() {
+		let mut registry = Registry::core();
+		let add = registry.register(OpSpec::new(1, "sub", 3, Some(2), |exec: &mut Exec| {
+			let value = exec.read(0)? - exec.read(1)?;
+			exec.write(2, value)?;
+			Ok(Flow::Next)
+		}));
+		assert_eq!(add.map(|spec| spec.mnemonic), Some("add"));
+
+		// the fast path must not use its built-in add
+		let program = vec![1101, 10, 3, 7, 4, 7, 99, 0];
+		let mut machine = Machine::with_registry(program.clone(), Arc::new(registry.clone()));
+		assert_eq!(machine.run_fast(), Ok(State::Produced(7)));
+
+		registry.unregister(1);
+		let mut machine = Machine::with_registry(program, Arc::new(registry));
+		assert_eq!(machine.run().map_err(|error| error.kind), Err(ErrorKind::InvalidOpcode(1)));
+	}
+
+	#[test]
+	#[should_panic(expected = "bad mnemonic")]
+	fn mnemonics_are_plain() {
+		OpSpec::<i64>::new(60, "say \"hi\"", 0, None, |_: &mut Exec| Ok(Flow::Next));
+	}
+}
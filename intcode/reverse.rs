@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+use super::cell::Cell;
+use super::machine::{Effect, Machine, Write};
+
+
+// Undo log of executed instructions, newest last. With a capacity the
+// oldest entries are forgotten, which bounds how far back one can go.
+#[derive(Debug, Clone)]
+pub struct UndoLog<C = i64> {
+	entries: VecDeque<Effect<C>>,
+	capacity: Option<usize>,
+}
+
+impl<C: Cell> Default for UndoLog<C> {
+	fn default() -> Self {
+		Self{entries: VecDeque::new(), capacity: None}
+	}
+}
+
+impl<C: Cell> UndoLog<C> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_capacity(capacity: usize) -> Self {
+		Self{entries: VecDeque::new(), capacity: Some(capacity)}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.entries.clear();
+	}
+
+	pub fn record(&mut self, effect: &Effect<C>) {
+		if self.capacity == Some(0) {
+			return;
+		}
+		if Some(self.entries.len()) == self.capacity {
+			self.entries.pop_front();
+		}
+		self.entries.push_back(effect.clone());
+	}
+
+	// Undoes the most recent instruction, returning what it had done.
+	pub fn step_back(&mut self, machine: &mut Machine<C>) -> Option<Effect<C>> {
+		let effect = self.entries.pop_back()?;
+		machine.unstep(&effect);
+
+		Some(effect)
+	}
+
+	// Rewinds to just before the most recent write to `addr`, leaving the
+	// writing instruction as the next one to execute. If no logged
+	// instruction wrote there, nothing is undone.
+	pub fn back_to_write(&mut self, machine: &mut Machine<C>, addr: usize)
+	-> Option<Write<C>> {
+		let index = self.entries.iter()
+			.rposition(|effect| effect.write.as_ref().map(|w| w.addr) == Some(addr))?;
+
+		let mut undone = None;
+		while self.entries.len() > index {
+			undone = self.step_back(machine);
+		}
+
+		undone.and_then(|effect| effect.write)
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::UndoLog;
+	use super::super::machine::{Machine, State, Write};
+	use super::super::memory::{Memory, MemoryKind};
+
+	// Runs to the end, logging every instruction that can be undone.
+	fn run_logged(machine: &mut Machine, log: &mut UndoLog) {
+		loop {
+			let state = machine.step().unwrap();
+			if let State::Running | State::Produced(_) = state {
+				log.record(machine.last_effect().unwrap());
+			}
+			if let State::Halted = state {
+				return;
+			}
+		}
+	}
+
+	#[test]
+	fn rewinds_to_the_start() {
+		// reads an input past the image, moves the relative base, writes
+		// through it and adds the two
+		let program = vec![3, 100, 109, 7, 21101, 2, 3, 50, 1, 100, 57, 200, 4, 200, 99];
+
+		for &kind in [MemoryKind::Dense, MemoryKind::Sparse].iter() {
+			let mut machine = Machine::from_memory(Memory::with_kind(program.clone(), kind));
+			machine.push_input(10);
+			machine.push_input(11);
+			let start = machine.clone();
+
+			let mut log = UndoLog::new();
+			run_logged(&mut machine, &mut log);
+			assert_eq!(machine.memory().peek(200), 15);
+			assert_eq!(machine.memory().len(), 201);
+			assert_eq!(machine.relative_base(), 7);
+			assert_eq!(machine.pending_inputs().len(), 1);
+
+			while log.step_back(&mut machine).is_some() {}
+			assert_eq!(machine.memory(), start.memory());
+			assert_eq!(machine.position(), start.position());
+			assert_eq!(machine.relative_base(), start.relative_base());
+			assert_eq!(machine.pending_inputs(), start.pending_inputs());
+			assert_eq!(machine.instructions_executed(), 0);
+		}
+	}
+
+	#[test]
+	fn back_to_a_self_modifying_write() {
+		// the second instruction turns the third into a halt
+		let mut machine = Machine::new(vec![1101, 1, 1, 12, 1101, 33, 66, 8, 1, 0, 0, 0, 0]);
+		let mut log = UndoLog::new();
+		run_logged(&mut machine, &mut log);
+		assert_eq!(machine.memory().peek(8), 99);
+
+		assert_eq!(log.back_to_write(&mut machine, 8), Some(Write{addr: 8, old: 1, new: 99}));
+		assert_eq!(machine.position(), 4);
+		assert_eq!(machine.memory().peek(8), 1);
+		assert_eq!(machine.memory().peek(12), 2);
+		assert_eq!(log.len(), 1);
+
+		assert_eq!(log.back_to_write(&mut machine, 5), None);
+		assert_eq!(machine.position(), 4);
+
+		assert_eq!(machine.run(), Ok(State::Halted));
+		assert_eq!(machine.memory().peek(8), 99);
+	}
+}
@@ -0,0 +1,265 @@
+// Exhaustive search over program inputs: memory patches (like day2's noun
+// and verb) and values fed to the input stream, each drawn from a range.
+// Candidates are run in parallel, and enumerated with the first variable
+// most significant, so `find_first` matches what nested loops would find.
+
+use std::cmp::min;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::vec::Vec;
+
+use super::error::Error;
+use super::machine::{Machine, State};
+use super::memory::Memory;
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Variable {
+	Patch{addr: usize, range: RangeInclusive<i64>},
+	Input{range: RangeInclusive<i64>},
+}
+
+impl Variable {
+	fn range(&self) -> &RangeInclusive<i64> {
+		match self {
+			Self::Patch{range, ..} | Self::Input{range} => range,
+		}
+	}
+
+	// `None` if there are too many values to count in a u64
+	fn len(&self) -> Option<u64> {
+		let range = self.range();
+		if range.start() > range.end() {
+			return Some(0);
+		}
+		u64::try_from(*range.end() as i128 - *range.start() as i128 + 1).ok()
+	}
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchError {
+	// a patch at or past the memory limit
+	AddressOutOfBounds(usize),
+	// more candidates than can be counted in a u64
+	TooLarge,
+}
+
+impl fmt::Display for SearchError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::AddressOutOfBounds(addr) => write!(
+				f, "patch address {} is beyond the memory limit", addr
+			),
+			Self::TooLarge => write!(f, "search space too large"),
+		}
+	}
+}
+
+impl std::error::Error for SearchError {}
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outcome {
+	pub memory: Vec<i64>,
+	pub outputs: Vec<i64>,
+	// `NeedsInput` if the program wanted more inputs than were searched over
+	pub state: Result<State, Error>,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct Search {
+	program: Vec<i64>,
+	variables: Vec<Variable>,
+	threads: usize,
+	budget: Option<u64>,
+}
+
+impl Search {
+	const CHUNK: u64 = 64;
+
+	pub fn new(program: Vec<i64>) -> Self {
+		Self{
+			program,
+			variables: Vec::new(),
+			threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+			budget: None,
+		}
+	}
+
+	pub fn add_patch(&mut self, addr: usize, range: RangeInclusive<i64>)
+	-> Result<(), SearchError> {
+		if addr >= Memory::DEFAULT_LIMIT {
+			return Err(SearchError::AddressOutOfBounds(addr));
+		}
+		self.variables.push(Variable::Patch{addr, range});
+
+		Ok(())
+	}
+
+	// Input variables are fed to the program in the order they were added.
+	pub fn add_input(&mut self, range: RangeInclusive<i64>) {
+		self.variables.push(Variable::Input{range});
+	}
+
+	pub fn set_threads(&mut self, threads: usize) {
+		self.threads = threads.max(1);
+	}
+
+	// Strongly advised: without it, a candidate that loops hangs the search.
+	pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+		self.budget = budget;
+	}
+
+	pub fn candidates(&self) -> Result<u64, SearchError> {
+		self.variables.iter()
+			.try_fold(1u64, |total, var| var.len().and_then(|len| total.checked_mul(len)))
+			.ok_or(SearchError::TooLarge)
+	}
+
+	// `lens` are the variables' lengths, which `candidates` has checked.
+	fn assignment(&self, lens: &[u64], mut index: u64) -> Vec<i64> {
+		let mut values = vec![0i64; self.variables.len()];
+		for (i, var) in self.variables.iter().enumerate().rev() {
+			values[i] = (*var.range().start() as i128 + (index % lens[i]) as i128) as i64;
+			index /= lens[i];
+		}
+		values
+	}
+
+	pub fn run(&self, values: &[i64]) -> Outcome {
+		let mut program = self.program.clone();
+		let mut inputs = Vec::<i64>::new();
+		for (var, &value) in self.variables.iter().zip(values.iter()) {
+			match var {
+				Variable::Patch{addr, ..} => {
+					if *addr >= program.len() {
+						program.resize(addr+1, 0);
+					}
+					program[*addr] = value;
+				},
+				Variable::Input{..} => inputs.push(value),
+			};
+		}
+
+		let mut machine = Machine::new(program);
+		machine.set_instruction_budget(self.budget);
+		for value in inputs {
+			machine.push_input(value);
+		}
+
+		let mut outputs = Vec::<i64>::new();
+		let state = loop {
+			match machine.run_fast() {
+				Ok(State::Produced(value)) => outputs.push(value),
+				state => break state,
+			}
+		};
+
+		Outcome{memory: machine.into_program(), outputs, state}
+	}
+
+	fn scan<F>(&self, predicate: &F, first_only: bool) -> Result<Vec<Vec<i64>>, SearchError>
+	where
+		F: Fn(&Outcome) -> bool + Sync,
+	{
+		let total = self.candidates()?;
+		let lens = self.variables.iter()
+			.map(|var| var.len().unwrap_or(0))
+			.collect::<Vec<_>>();
+		let next = AtomicU64::new(0);
+		// lowest matching index so far, so later candidates can be skipped
+		let best = AtomicU64::new(u64::MAX);
+		let found = Mutex::new(Vec::<(u64, Vec<i64>)>::new());
+
+		let worker = || loop {
+			let start = next.fetch_add(Self::CHUNK, Ordering::Relaxed);
+			if start >= total {
+				break;
+			}
+
+			for index in start..min(start.saturating_add(Self::CHUNK), total) {
+				if first_only && index >= best.load(Ordering::Relaxed) {
+					return;
+				}
+
+				let values = self.assignment(&lens, index);
+				if predicate(&self.run(&values)) {
+					best.fetch_min(index, Ordering::Relaxed);
+					found.lock().unwrap().push((index, values));
+				}
+			}
+		};
+
+		std::thread::scope(|scope| {
+			for _ in 0..self.threads {
+				scope.spawn(worker);
+			}
+		});
+
+		let mut found = found.into_inner().unwrap();
+		found.sort();
+		if first_only {
+			found.truncate(1);
+		}
+		Ok(found.into_iter().map(|(_, values)| values).collect())
+	}
+
+	// The first satisfying assignment, one value per variable in the order
+	// the variables were added.
+	pub fn find_first<F>(&self, predicate: F) -> Result<Option<Vec<i64>>, SearchError>
+	where
+		F: Fn(&Outcome) -> bool + Sync,
+	{
+		Ok(self.scan(&predicate, true)?.pop())
+	}
+
+	pub fn find_all<F>(&self, predicate: F) -> Result<Vec<Vec<i64>>, SearchError>
+	where
+		F: Fn(&Outcome) -> bool + Sync,
+	{
+		self.scan(&predicate, false)
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{Search, SearchError};
+	use super::super::memory::Memory;
+
+	#[test]
+	fn finds_in_nested_loop_order() {
+		// address 0 ends up as the sum of the cells the noun and verb point at
+		let mut search = Search::new(vec![1, 0, 0, 0, 99]);
+		search.add_patch(1, 0..=4).unwrap();
+		search.add_patch(2, 0..=4).unwrap();
+		search.set_threads(3);
+		assert_eq!(search.candidates(), Ok(25));
+
+		let sums_to_100 = |outcome: &super::Outcome| outcome.memory[0] == 100;
+		assert_eq!(search.find_first(sums_to_100), Ok(Some(vec![0, 4])));
+		assert_eq!(search.find_all(sums_to_100), Ok(vec![vec![0, 4], vec![1, 4], vec![4, 0]]));
+		assert_eq!(search.find_first(|_| false), Ok(None));
+	}
+
+	#[test]
+	fn rejects_what_it_cannot_search() {
+		let mut search = Search::new(vec![99]);
+		assert_eq!(
+			search.add_patch(Memory::DEFAULT_LIMIT, 0..=1),
+			Err(SearchError::AddressOutOfBounds(Memory::DEFAULT_LIMIT))
+		);
+
+		search.add_patch(0, i64::MIN..=i64::MAX).unwrap();
+		search.add_patch(1, 0..=1).unwrap();
+		assert_eq!(search.candidates(), Err(SearchError::TooLarge));
+		assert_eq!(search.find_first(|_| true), Err(SearchError::TooLarge));
+		assert_eq!(search.find_all(|_| true), Err(SearchError::TooLarge));
+	}
+}
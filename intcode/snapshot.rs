@@ -0,0 +1,299 @@
+// Machine checkpoints, saved as plain text:
+//
+//     intcode-snapshot 1
+//     ip 12
+//     relative_base 0
+//     executed 57
+//     budget none
+//     loop_detection false
+//     memory_limit 16777216
+//     memory_kind dense
+//     arithmetic checked
+//     inputs 5,7
+//     memory_len 5000
+//     memory 0:1002,4,3,4,33 4096:7,8
+//
+// Memory is saved as its length and the runs of non-zero cells, each as
+// its start address and values, so that a far write doesn't save every zero
+// below it. Dense memory is allocated in full when the snapshot is loaded,
+// so snapshots with more of it than `MAX_DENSE_LEN` cells are refused.
+//
+// A snapshot captures everything needed to resume the machine exactly;
+// outputs are never buffered inside a machine, so there are none to save.
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::vec::Vec;
+
+use super::machine::Arithmetic;
+use super::memory::{Memory, MemoryKind};
+
+const HEADER: &str = "intcode-snapshot 1";
+const MAX_DENSE_LEN: usize = Memory::DEFAULT_LIMIT;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<C = i64> {
+	pub pos: usize,
+	pub relative_base: i64,
+	pub executed: u64,
+	pub budget: Option<u64>,
+	pub loop_detection: bool,
+	pub memory_limit: usize,
+	pub memory_kind: MemoryKind,
+	pub arithmetic: Arithmetic,
+	pub inputs: Vec<C>,
+	pub memory_len: usize,
+	// runs of non-zero cells as (address, values), as from `Memory::runs`
+	pub memory: Vec<(usize, Vec<C>)>,
+}
+
+fn join<C: Display>(values: &[C]) -> String {
+	values.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+}
+
+fn invalid_data(message: String) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl<C: Display + FromStr> Snapshot<C> {
+	pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+		writeln!(writer, "{}", HEADER)?;
+		writeln!(writer, "ip {}", self.pos)?;
+		writeln!(writer, "relative_base {}", self.relative_base)?;
+		writeln!(writer, "executed {}", self.executed)?;
+		match self.budget {
+			Some(budget) => writeln!(writer, "budget {}", budget)?,
+			None => writeln!(writer, "budget none")?,
+		};
+		writeln!(writer, "loop_detection {}", self.loop_detection)?;
+		writeln!(writer, "memory_limit {}", self.memory_limit)?;
+		writeln!(writer, "memory_kind {}", match self.memory_kind {
+			MemoryKind::Dense => "dense",
+			MemoryKind::Sparse => "sparse",
+		})?;
+		writeln!(writer, "arithmetic {}", match self.arithmetic {
+			Arithmetic::Checked => "checked",
+			Arithmetic::Wrapping => "wrapping",
+		})?;
+		writeln!(writer, "inputs {}", join(&self.inputs))?;
+		writeln!(writer, "memory_len {}", self.memory_len)?;
+		let runs = self.memory.iter()
+			.map(|(start, values)| format!("{}:{}", start, join(values)))
+			.collect::<Vec<_>>();
+		writeln!(writer, "memory {}", runs.join(" "))?;
+
+		writer.flush()
+	}
+
+	pub fn read_from<R: BufRead>(reader: R) -> io::Result<Snapshot<C>> {
+		let mut lines = reader.lines();
+		if lines.next().transpose()?.as_ref().map(|l| l.trim()) != Some(HEADER) {
+			return Err(invalid_data("not an intcode snapshot".to_string()));
+		}
+
+		let mut field = |name: &str| -> io::Result<String> {
+			let line = lines.next().transpose()?.ok_or(
+				invalid_data(format!("missing field '{}'", name))
+			)?;
+			let mut parts = line.trim_end().splitn(2, ' ');
+			if parts.next() != Some(name) {
+				return Err(invalid_data(format!("expected field '{}'", name)));
+			}
+
+			Ok(parts.next().unwrap_or("").to_string())
+		};
+		fn parse<T: FromStr>(name: &str, text: &str) -> io::Result<T> {
+			text.trim().parse::<T>().map_err(|_| invalid_data(
+				format!("invalid value '{}' for field '{}'", text, name)
+			))
+		}
+		fn parse_list<T: FromStr>(name: &str, text: &str) -> io::Result<Vec<T>> {
+			text.split(',')
+				.filter(|word| !word.trim().is_empty())
+				.map(|word| parse(name, word))
+				.collect()
+		}
+
+		let pos = parse("ip", &field("ip")?)?;
+		let relative_base = parse("relative_base", &field("relative_base")?)?;
+		let executed = parse("executed", &field("executed")?)?;
+		let budget = match field("budget")?.trim() {
+			"none" => None,
+			text => Some(parse("budget", text)?),
+		};
+		let loop_detection = parse("loop_detection", &field("loop_detection")?)?;
+		let memory_limit = parse("memory_limit", &field("memory_limit")?)?;
+		let memory_kind = match field("memory_kind")?.trim() {
+			"dense" => MemoryKind::Dense,
+			"sparse" => MemoryKind::Sparse,
+			text => return Err(invalid_data(
+				format!("invalid value '{}' for field 'memory_kind'", text)
+			)),
+		};
+		let arithmetic = match field("arithmetic")?.trim() {
+			"checked" => Arithmetic::Checked,
+			"wrapping" => Arithmetic::Wrapping,
+			text => return Err(invalid_data(
+				format!("invalid value '{}' for field 'arithmetic'", text)
+			)),
+		};
+		let inputs = parse_list("inputs", &field("inputs")?)?;
+		let memory_len = parse("memory_len", &field("memory_len")?)?;
+		let memory: Vec<(usize, Vec<C>)> = field("memory")?.split_whitespace()
+			.map(|run| match run.split_once(':') {
+				Some((start, values)) => Ok((
+					parse("memory", start)?,
+					parse_list("memory", values)?,
+				)),
+				None => Err(invalid_data(format!("invalid memory run '{}'", run))),
+			})
+			.collect::<io::Result<_>>()?;
+		if memory_len > memory_limit {
+			return Err(invalid_data(format!(
+				"memory_len {} is over memory_limit {}", memory_len, memory_limit
+			)));
+		}
+		if memory_kind == MemoryKind::Dense && memory_len > MAX_DENSE_LEN {
+			return Err(invalid_data(format!(
+				"memory_len {} is too large for dense memory", memory_len
+			)));
+		}
+		for (start, values) in memory.iter() {
+			if start.checked_add(values.len()).is_none_or(|end| end > memory_len) {
+				return Err(invalid_data(format!(
+					"memory run at {} goes past memory_len {}", start, memory_len
+				)));
+			}
+		}
+
+		Ok(Snapshot{
+			pos,
+			relative_base,
+			executed,
+			budget,
+			loop_detection,
+			memory_limit,
+			memory_kind,
+			arithmetic,
+			inputs,
+			memory_len,
+			memory,
+		})
+	}
+
+	pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+		self.write_to(BufWriter::new(File::create(path)?))
+	}
+
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Snapshot<C>> {
+		Self::read_from(BufReader::new(File::open(path)?))
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::Snapshot;
+	use super::super::machine::{Arithmetic, Machine, State};
+	use super::super::memory::{Memory, MemoryKind};
+	use std::io::ErrorKind;
+
+	fn round_trip(snapshot: &Snapshot) -> (String, Snapshot) {
+		let mut text = Vec::new();
+		snapshot.write_to(&mut text).unwrap();
+		let read = Snapshot::read_from(&text[..]).unwrap();
+		(String::from_utf8(text).unwrap(), read)
+	}
+
+	#[test]
+	fn round_trip_resumes_the_machine() {
+		let mut machine = Machine::new(vec![3, 20, 3, 21, 1, 20, 21, 22, 4, 22, 99]);
+		machine.set_instruction_budget(Some(100));
+		machine.push_input(5);
+		machine.push_input(7);
+		machine.step().unwrap();
+
+		let (text, snapshot) = round_trip(&machine.snapshot());
+		assert_eq!(snapshot, machine.snapshot());
+		assert!(text.contains("\ninputs 7\nmemory_len 21\nmemory 0:3,20,3,21,1,20,21,22,4,22,99 20:5\n"));
+
+		let mut restored = Machine::from_snapshot(snapshot);
+		assert_eq!(restored.memory(), machine.memory());
+		assert_eq!(restored.run(), Ok(State::Produced(12)));
+		assert_eq!(restored.instructions_executed(), 4);
+	}
+
+	#[test]
+	fn far_writes_save_only_what_was_written() {
+		let far = Memory::DEFAULT_LIMIT - 10;
+		let mut machine = Machine::from_memory(Memory::sparse(vec![1101, 3, 4, far as i64, 99]));
+		assert_eq!(machine.run(), Ok(State::Halted));
+
+		let (text, snapshot) = round_trip(&machine.snapshot());
+		assert!(text.len() < 300);
+		assert_eq!(snapshot.memory_len, far + 1);
+
+		let mut restored = Machine::from_memory(Memory::sparse(Vec::new()));
+		restored.restore(snapshot);
+		assert_eq!(restored.memory(), machine.memory());
+		assert_eq!(restored.memory().peek(far), 7);
+		assert_eq!(restored.position(), 4);
+	}
+
+	#[test]
+	fn keeps_wrapping_arithmetic() {
+		let mut machine = Machine::new(vec![1102, i64::MAX, 2, 5, 99, 0]);
+		machine.set_arithmetic(Arithmetic::Wrapping);
+
+		let (text, snapshot) = round_trip(&machine.snapshot());
+		assert!(text.contains("\narithmetic wrapping\n"));
+		let mut restored = Machine::from_snapshot(snapshot);
+		assert_eq!(restored.arithmetic(), Arithmetic::Wrapping);
+		assert_eq!(restored.run(), Ok(State::Halted));
+		assert_eq!(restored.memory().peek(5), -2);
+	}
+
+	#[test]
+	fn rejects_memory_past_the_limit() {
+		let text = |limit: &str, kind: &str, memory_len: &str, memory: &str| format!(
+			"intcode-snapshot 1\nip 0\nrelative_base 0\nexecuted 0\nbudget none\n\
+			loop_detection false\nmemory_limit {}\nmemory_kind {}\narithmetic checked\n\
+			inputs \nmemory_len {}\nmemory {}\n",
+			limit, kind, memory_len, memory,
+		);
+		let read = |text: String| Snapshot::<i64>::read_from(text.as_bytes())
+			.map(|snapshot| snapshot.memory_len)
+			.map_err(|error| error.kind());
+		let max = usize::MAX.to_string();
+
+		assert_eq!(read(text("100", "dense", &max, "0:99")), Err(ErrorKind::InvalidData));
+		assert_eq!(read(text("100", "dense", "101", "0:99")), Err(ErrorKind::InvalidData));
+		assert_eq!(read(text("100", "dense", "10", "8:1,2,3")), Err(ErrorKind::InvalidData));
+		assert_eq!(read(text("100", "dense", "10", &format!("{}:1", max))), Err(ErrorKind::InvalidData));
+		assert_eq!(read(text("100", "dense", "100", "97:1,2,3")), Ok(100));
+
+		// only sparse memory can be that large without allocating all of it
+		assert_eq!(read(text(&max, "dense", &max, "0:99")), Err(ErrorKind::InvalidData));
+		assert_eq!(read(text(&max, "sparse", &max, "0:99")), Ok(usize::MAX));
+	}
+
+	#[test]
+	fn sparse_machines_reload_sparse() {
+		let far = 1usize << 40;
+		let mut memory = Memory::sparse(vec![1101, 3, 4, far as i64, 99]);
+		memory.set_limit(far + 1);
+		let mut machine = Machine::from_memory(memory);
+		assert_eq!(machine.run(), Ok(State::Halted));
+
+		let (text, snapshot) = round_trip(&machine.snapshot());
+		assert!(text.contains("\nmemory_kind sparse\n"));
+		let restored = Machine::from_snapshot(snapshot);
+		assert_eq!(restored.memory().kind(), MemoryKind::Sparse);
+		assert_eq!(restored.memory(), machine.memory());
+		assert_eq!(restored.memory().peek(far), 7);
+	}
+}
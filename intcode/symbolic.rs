@@ -0,0 +1,354 @@
+// Symbolic execution of add/multiply-only programs (the day2 instruction
+// set), with the noun and verb cells treated as variables. Every cell then
+// holds a polynomial in the noun and verb, so the final value at address 0
+// can be solved for directly instead of running the program 10,000 times.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::vec::Vec;
+
+use super::disasm::{decode, Operand};
+use super::opcode::OpInstruction;
+
+
+// Polynomial in n (noun) and v (verb): exponents of (n, v) -> coefficient.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poly(BTreeMap<(u32, u32), i64>);
+
+impl Poly {
+	pub fn constant(value: i64) -> Poly {
+		let mut terms = BTreeMap::new();
+		if value != 0 {
+			terms.insert((0, 0), value);
+		}
+		Poly(terms)
+	}
+
+	pub fn noun() -> Poly {
+		Poly(vec![((1, 0), 1)].into_iter().collect())
+	}
+
+	pub fn verb() -> Poly {
+		Poly(vec![((0, 1), 1)].into_iter().collect())
+	}
+
+	pub fn as_constant(&self) -> Option<i64> {
+		match self.0.len() {
+			0 => Some(0),
+			1 => self.0.get(&(0, 0)).cloned(),
+			_ => None,
+		}
+	}
+
+	pub fn checked_add(&self, other: &Poly) -> Option<Poly> {
+		let mut terms = self.0.clone();
+		for (&key, &coeff) in other.0.iter() {
+			let sum = terms.get(&key).unwrap_or(&0).checked_add(coeff)?;
+			match sum {
+				0 => terms.remove(&key),
+				_ => terms.insert(key, sum),
+			};
+		}
+		Some(Poly(terms))
+	}
+
+	pub fn checked_mul(&self, other: &Poly) -> Option<Poly> {
+		let mut product = Poly::constant(0);
+		for (&(n1, v1), &c1) in self.0.iter() {
+			for (&(n2, v2), &c2) in other.0.iter() {
+				let mut term = BTreeMap::new();
+				term.insert(
+					(n1.checked_add(n2)?, v1.checked_add(v2)?),
+					c1.checked_mul(c2)?,
+				);
+				product = product.checked_add(&Poly(term))?;
+			}
+		}
+		Some(product)
+	}
+
+	pub fn eval(&self, noun: i64, verb: i64) -> Option<i64> {
+		let mut total = 0i64;
+		for (&(n, v), &coeff) in self.0.iter() {
+			total = total.checked_add(
+				coeff.checked_mul(noun.checked_pow(n)?)?.checked_mul(verb.checked_pow(v)?)?
+			)?;
+		}
+		Some(total)
+	}
+
+	// The non-zero terms of the polynomial in v left after fixing n, as
+	// exponent of v -> coefficient. Exponents can be huge (repeated squaring
+	// doubles them), so the terms are kept sparse.
+	fn in_verb(&self, noun: i64) -> Option<BTreeMap<u32, i64>> {
+		let mut coeffs = BTreeMap::<u32, i64>::new();
+		for (&(n, v), &coeff) in self.0.iter() {
+			let term = coeff.checked_mul(noun.checked_pow(n)?)?;
+			let sum = coeffs.get(&v).unwrap_or(&0).checked_add(term)?;
+			match sum {
+				0 => coeffs.remove(&v),
+				_ => coeffs.insert(v, sum),
+			};
+		}
+		Some(coeffs)
+	}
+}
+
+impl fmt::Display for Poly {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.0.is_empty() {
+			return write!(f, "0");
+		}
+
+		for (i, (&(n, v), &coeff)) in self.0.iter().enumerate() {
+			let sign = if coeff < 0 {"-"} else if i > 0 {"+"} else {""};
+			let spaced = if i > 0 {format!(" {} ", sign)} else {sign.to_string()};
+			write!(f, "{}", spaced)?;
+
+			let mut factors = Vec::<String>::new();
+			if coeff.unsigned_abs() != 1 || (n, v) == (0, 0) {
+				factors.push(coeff.unsigned_abs().to_string());
+			}
+			for (name, exp) in [("noun", n), ("verb", v)].iter() {
+				match exp {
+					0 => {},
+					1 => factors.push(name.to_string()),
+					e => factors.push(format!("{}^{}", name, e)),
+				};
+			}
+			write!(f, "{}", factors.join("*"))?;
+		}
+		Ok(())
+	}
+}
+
+//-----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolicError {
+	// an opcode or write address at this position depends on noun/verb
+	DependsOnSymbols(usize),
+	// the instruction at this position is not add, multiply or halt
+	Unsupported(usize),
+	// the result itself could not be tracked (e.g. read through a
+	// noun/verb-dependent address)
+	UnknownResult,
+	Overflow(usize),
+}
+
+impl fmt::Display for SymbolicError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::DependsOnSymbols(pos) => write!(
+				f, "control or addressing at {} depends on the noun/verb", pos
+			),
+			Self::Unsupported(pos) => write!(
+				f, "unsupported instruction at {}", pos
+			),
+			Self::UnknownResult => write!(f, "result depends on unknown data"),
+			Self::Overflow(pos) => write!(f, "coefficient overflow at {}", pos),
+		}
+	}
+}
+
+
+// Cells are `None` once their contents can't be tracked: the value was read
+// through an address that depends on the noun or verb. That is harmless
+// unless the value is later needed as an opcode, an address or the result.
+fn concrete(cell: &Option<Poly>) -> Option<i64> {
+	cell.as_ref().and_then(|poly| poly.as_constant())
+}
+
+// Runs `program` with the cells at 1 and 2 as the noun and verb, returning
+// the closed form of the final value at address 0.
+pub fn symbolic_result(program: &[i64]) -> Result<Poly, SymbolicError> {
+	if program.len() < 3 {
+		return Err(SymbolicError::Unsupported(0));
+	}
+	let mut cells: Vec<Option<Poly>> = program.iter()
+		.map(|&word| Some(Poly::constant(word)))
+		.collect();
+	cells[1] = Some(Poly::noun());
+	cells[2] = Some(Poly::verb());
+
+	let mut pos = 0usize;
+	while pos < cells.len() {
+		// decode against a concrete view of the instruction's words
+		let mut words = Vec::<i64>::new();
+		for cell in cells[pos..].iter().take(4) {
+			words.push(concrete(cell).unwrap_or(0));
+		}
+		if concrete(&cells[pos]).is_none() {
+			return Err(SymbolicError::DependsOnSymbols(pos));
+		}
+		let instruction = decode(&words, 0).ok_or(SymbolicError::Unsupported(pos))?;
+
+		let op = instruction.op;
+		match op {
+			OpInstruction::Terminate => break,
+			OpInstruction::Add | OpInstruction::Multiply => {},
+			_ => return Err(SymbolicError::Unsupported(pos)),
+		};
+
+		let mut args = Vec::<Option<Poly>>::new();
+		for (i, operand) in instruction.operands[..2].iter().enumerate() {
+			args.push(match operand {
+				Operand::Immediate(_) => cells[pos+1+i].clone(),
+				Operand::Position(_) => match concrete(&cells[pos+1+i]) {
+					Some(addr) => usize::try_from(addr).ok()
+						.and_then(|addr| cells.get(addr))
+						.cloned()
+						.ok_or(SymbolicError::Unsupported(pos))?,
+					None => None,
+				},
+				Operand::Relative(_) => return Err(SymbolicError::Unsupported(pos)),
+			});
+		}
+
+		let target = match instruction.operands[2] {
+			Operand::Position(_) => concrete(&cells[pos+3])
+				.ok_or(SymbolicError::DependsOnSymbols(pos))?,
+			_ => return Err(SymbolicError::Unsupported(pos)),
+		};
+		let target = usize::try_from(target).ok()
+			.filter(|&target| target < cells.len())
+			.ok_or(SymbolicError::Unsupported(pos))?;
+
+		cells[target] = match (&args[0], &args[1]) {
+			(Some(a), Some(b)) => Some(match op {
+				OpInstruction::Add => a.checked_add(b),
+				_ => a.checked_mul(b),
+			}.ok_or(SymbolicError::Overflow(pos))?),
+			_ => None,
+		};
+		pos += 4;
+	}
+
+	cells[0].clone().ok_or(SymbolicError::UnknownResult)
+}
+
+// The first (noun, verb) in noun-major order for which `result` equals
+// `expected`, the same order a brute-force search would find.
+pub fn solve_noun_verb(
+	result: &Poly, expected: i64, range: RangeInclusive<i64>
+) -> Option<(i64, i64)> {
+	for noun in range.clone() {
+		let coeffs = match result.in_verb(noun) {
+			Some(coeffs) => coeffs,
+			None => continue,
+		};
+
+		let coeff = |exp| coeffs.get(&exp).cloned().unwrap_or(0);
+		match coeffs.keys().next_back().cloned().unwrap_or(0) {
+			0 => {
+				// constant in the verb: either every verb works or none
+				if coeff(0) == expected {
+					return Some((noun, *range.start()));
+				}
+			},
+			1 => {
+				let diff = match expected.checked_sub(coeff(0)) {
+					Some(diff) => diff,
+					None => continue,
+				};
+				if diff.checked_rem(coeff(1)) == Some(0) {
+					let verb = diff / coeff(1);
+					if range.contains(&verb) {
+						return Some((noun, verb));
+					}
+				}
+			},
+			_ => {
+				let verb = range.clone()
+					.find(|&verb| result.eval(noun, verb) == Some(expected));
+				if let Some(verb) = verb {
+					return Some((noun, verb));
+				}
+			},
+		};
+	}
+
+	None
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use super::{solve_noun_verb, symbolic_result, Poly};
+	use super::super::machine::Machine;
+
+	fn day2_program() -> Vec<i64> {
+		include_str!("../day2/edited-input.txt").trim().split(',')
+			.map(|word| word.trim().parse().unwrap())
+			.collect()
+	}
+
+	fn brute_force(program: &[i64], expected: i64) -> Option<(i64, i64)> {
+		for noun in 0..=99 {
+			for verb in 0..=99 {
+				let mut patched = program.to_vec();
+				patched[1] = noun;
+				patched[2] = verb;
+				let mut machine = Machine::new(patched);
+				if machine.run().is_ok() && machine.memory().read(0) == Ok(expected) {
+					return Some((noun, verb));
+				}
+			}
+		}
+		None
+	}
+
+	#[test]
+	fn matches_brute_force_on_day2() {
+		let program = day2_program();
+		let result = symbolic_result(&program).unwrap();
+
+		for &(noun, verb) in [(12, 2), (49, 67), (0, 0), (99, 99), (3, 50)].iter() {
+			let expected = result.eval(noun, verb).unwrap();
+			assert_eq!(
+				solve_noun_verb(&result, expected, 0..=99),
+				brute_force(&program, expected),
+			);
+		}
+		assert_eq!(solve_noun_verb(&result, -1, 0..=99), None);
+		assert_eq!(brute_force(&program, -1), None);
+	}
+
+	#[test]
+	fn finds_the_first_of_several_solutions() {
+		// address 0 = noun * verb
+		let program = [1102, 0, 0, 0, 99];
+		let result = symbolic_result(&program).unwrap();
+		assert_eq!(result, Poly::noun().checked_mul(&Poly::verb()).unwrap());
+
+		for &expected in [0, 12, 97, 9801, 9802].iter() {
+			assert_eq!(
+				solve_noun_verb(&result, expected, 0..=99),
+				brute_force(&program, expected),
+			);
+		}
+	}
+
+	#[test]
+	fn huge_exponents_are_searched() {
+		// squares the verb 31 times, so address 0 = verb^2147483648
+		let mut program = vec![1, 0, 0, 3];
+		for _ in 0..31 {
+			program.extend_from_slice(&[2, 2, 2, 2]);
+		}
+		program.extend_from_slice(&[1001, 2, 0, 0, 99]);
+		assert_eq!(program.len(), 133);
+
+		let result = symbolic_result(&program).unwrap();
+		assert_eq!(result.to_string(), "verb^2147483648");
+		for &expected in [0, 1, 5].iter() {
+			assert_eq!(
+				solve_noun_verb(&result, expected, 0..=99),
+				brute_force(&program, expected),
+			);
+		}
+	}
+}
@@ -0,0 +1,463 @@
+// Instruction-level execution traces.
+//
+// JSON Lines writes one object per executed instruction:
+//
+//     {"step":0,"ip":0,"word":1002,"op":"mul","rb":0,"operands":[4,3,4],
+//      "write":{"addr":4,"old":33,"new":99}}
+//
+// with "write", "input" and "output" present only when they happened, and
+// `null` for an operand that wasn't read, such as the target of a jump that
+// isn't taken.
+//
+// The binary format starts with the magic bytes `ICTRACE1`, followed by one
+// record per instruction: ip as an unsigned LEB128 varint, the word and
+// relative base as zigzag varints, an operand count byte, whose high four
+// bits flag the operands that weren't read, and the values of the rest as
+// zigzag varints, then a flags byte (1 = write, 2 = input,
+// 4 = output, 8 = mnemonic, 16 = not counted, as for a halt) and the
+// flagged fields in that order. A write is its address (unsigned) followed
+// by the old and new values; the mnemonic, stored only for instructions
+// outside the core set, is its length (unsigned) followed by its UTF-8
+// bytes.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write as IoWrite};
+use std::vec::Vec;
+
+use super::cell::Cell;
+use super::error::Error;
+use super::io::{InputSource, OutputSink};
+use super::machine::{Effect, Machine, State, Write};
+use super::opcode::OpInstruction;
+use super::registry::{intern, valid_mnemonic};
+
+
+const MAGIC: &[u8; 8] = b"ICTRACE1";
+// longer ones only turn up in corrupt traces
+const MAX_MNEMONIC: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+	JsonLines,
+	Binary,
+}
+
+
+#[derive(Debug)]
+pub struct Tracer<W: IoWrite> {
+	writer: W,
+	format: TraceFormat,
+	steps: u64,
+	// the first write failure; tracing stops after it
+	error: Option<io::Error>,
+}
+
+impl<W: IoWrite> Tracer<W> {
+	pub fn new(mut writer: W, format: TraceFormat) -> Self {
+		let error = match format {
+			TraceFormat::Binary => writer.write_all(MAGIC).err(),
+			TraceFormat::JsonLines => None,
+		};
+
+		Self{writer, format, steps: 0, error}
+	}
+
+	pub fn steps(&self) -> u64 {
+		self.steps
+	}
+
+	pub fn record<C: Cell>(&mut self, effect: &Effect<C>) {
+		if self.error.is_some() {
+			return;
+		}
+
+		let result = match self.format {
+			TraceFormat::JsonLines => write_json(&mut self.writer, self.steps, effect),
+			TraceFormat::Binary => write_binary(&mut self.writer, effect),
+		};
+		self.error = result.err();
+		self.steps += 1;
+	}
+
+	// Same as `Machine::run`, recording every executed instruction.
+	pub fn run<C: Cell>(&mut self, machine: &mut Machine<C>) -> Result<State<C>, Error<C>> {
+		loop {
+			let state = machine.step()?;
+			if let Some(effect) = machine.last_effect() {
+				self.record(effect);
+			}
+
+			match state {
+				State::Running => continue,
+				state => return Ok(state),
+			}
+		}
+	}
+
+	pub fn run_with<C, I, O>(
+		&mut self, machine: &mut Machine<C>, input: &mut I, output: &mut O
+	) -> Result<State<C>, Error<C>>
+	where
+		C: Cell,
+		I: InputSource<C> + ?Sized,
+		O: OutputSink<C> + ?Sized,
+	{
+		machine.drive(&mut (input, output), |machine| self.run(machine))
+	}
+
+	pub fn finish(mut self) -> io::Result<W> {
+		if let Some(error) = self.error.take() {
+			return Err(error);
+		}
+		self.writer.flush()?;
+
+		Ok(self.writer)
+	}
+}
+
+fn operand_count<C>(effect: &Effect<C>) -> usize {
+	usize::from(effect.arity).min(effect.values.len())
+}
+
+//-----------------------------------------------------------------------------
+
+fn write_json<W: IoWrite, C: Cell>(writer: &mut W, step: u64, effect: &Effect<C>)
+-> io::Result<()> {
+	let operands = effect.values[..operand_count(effect)].iter().enumerate()
+		.map(|(i, v)| match effect.resolved & 1 << i {
+			0 => "null".to_string(),
+			_ => v.to_string(),
+		})
+		.collect::<Vec<_>>()
+		.join(",");
+
+	write!(
+		writer,
+		"{{\"step\":{},\"ip\":{},\"word\":{},\"op\":\"{}\",\"rb\":{},\"operands\":[{}]",
+		step, effect.pos, effect.word,
+		match effect.mnemonic {
+			"" => "?",
+			mnemonic => mnemonic,
+		},
+		effect.relative_base, operands,
+	)?;
+	if let Some(write) = &effect.write {
+		write!(
+			writer, ",\"write\":{{\"addr\":{},\"old\":{},\"new\":{}}}",
+			write.addr, write.old, write.new
+		)?;
+	}
+	if let Some(value) = &effect.input {
+		write!(writer, ",\"input\":{}", value)?;
+	}
+	if let Some(value) = &effect.output {
+		write!(writer, ",\"output\":{}", value)?;
+	}
+
+	writeln!(writer, "}}")
+}
+
+
+fn write_uvarint<W: IoWrite>(writer: &mut W, mut value: u64) -> io::Result<()> {
+	loop {
+		let byte = (value & 0x7f) as u8;
+		value >>= 7;
+		if value == 0 {
+			return writer.write_all(&[byte]);
+		}
+		writer.write_all(&[byte | 0x80])?;
+	}
+}
+
+fn write_varint<W: IoWrite>(writer: &mut W, value: i64) -> io::Result<()> {
+	write_uvarint(writer, ((value << 1) ^ (value >> 63)) as u64)
+}
+
+// The binary format only has room for values that fit in an i64.
+fn write_cell<W: IoWrite, C: Cell>(writer: &mut W, value: &C) -> io::Result<()> {
+	match value.to_i64() {
+		Some(value) => write_varint(writer, value),
+		None => Err(invalid_data("value too large for a binary trace")),
+	}
+}
+
+fn write_binary<W: IoWrite, C: Cell>(writer: &mut W, effect: &Effect<C>) -> io::Result<()> {
+	write_uvarint(writer, effect.pos as u64)?;
+	write_cell(writer, &effect.word)?;
+	write_varint(writer, effect.relative_base)?;
+	let count = operand_count(effect);
+	let unread = !effect.resolved & ((1 << count) - 1);
+	writer.write_all(&[count as u8 | unread << 4])?;
+	for (i, value) in effect.values[..count].iter().enumerate() {
+		if unread & 1 << i == 0 {
+			write_cell(writer, value)?;
+		}
+	}
+
+	let custom = effect.op.is_none() && !effect.mnemonic.is_empty();
+	let flags = (effect.write.is_some() as u8)
+		| (effect.input.is_some() as u8) << 1
+		| (effect.output.is_some() as u8) << 2
+		| (custom as u8) << 3
+		| (!effect.counted as u8) << 4;
+	writer.write_all(&[flags])?;
+
+	if let Some(write) = &effect.write {
+		write_uvarint(writer, write.addr as u64)?;
+		write_cell(writer, &write.old)?;
+		write_cell(writer, &write.new)?;
+	}
+	if let Some(value) = &effect.input {
+		write_cell(writer, value)?;
+	}
+	if let Some(value) = &effect.output {
+		write_cell(writer, value)?;
+	}
+	if custom {
+		write_uvarint(writer, effect.mnemonic.len() as u64)?;
+		writer.write_all(effect.mnemonic.as_bytes())?;
+	}
+
+	Ok(())
+}
+
+//-----------------------------------------------------------------------------
+
+fn invalid_data(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// `Ok(None)` only on a clean end of stream before the first byte
+fn read_uvarint<R: Read>(reader: &mut R) -> io::Result<Option<u64>> {
+	let mut value = 0u64;
+	let mut shift = 0u32;
+
+	loop {
+		let mut byte = [0u8];
+		if reader.read(&mut byte)? == 0 {
+			return match shift {
+				0 => Ok(None),
+				_ => Err(invalid_data("truncated varint")),
+			};
+		}
+		if shift >= 64 {
+			return Err(invalid_data("varint too long"));
+		}
+
+		value |= u64::from(byte[0] & 0x7f) << shift;
+		shift += 7;
+		if byte[0] & 0x80 == 0 {
+			return Ok(Some(value));
+		}
+	}
+}
+
+fn read_value<R: Read>(reader: &mut R) -> io::Result<u64> {
+	read_uvarint(reader)?.ok_or(invalid_data("truncated record"))
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> io::Result<i64> {
+	let value = read_value(reader)?;
+	Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+}
+
+fn read_addr<R: Read>(reader: &mut R) -> io::Result<usize> {
+	usize::try_from(read_value(reader)?).map_err(|_| invalid_data("bad address"))
+}
+
+// Reads a binary trace back into the effects that produced it. Traces do
+// not record `memory_len`, so it is left at zero.
+pub fn read_binary<R: Read>(mut reader: R) -> io::Result<Vec<Effect>> {
+	let mut magic = [0u8; 8];
+	reader.read_exact(&mut magic)?;
+	if &magic != MAGIC {
+		return Err(invalid_data("not an intcode trace"));
+	}
+
+	let mut effects = Vec::<Effect>::new();
+	while let Some(pos) = read_uvarint(&mut reader)? {
+		let mut effect = Effect{
+			pos: usize::try_from(pos).map_err(|_| invalid_data("bad address"))?,
+			word: read_varint(&mut reader)?,
+			relative_base: read_varint(&mut reader)?,
+			..Effect::default()
+		};
+		let mut count = [0u8];
+		reader.read_exact(&mut count)?;
+		effect.arity = count[0] & 0x0f;
+		if usize::from(effect.arity) > effect.values.len() {
+			return Err(invalid_data("too many operands"));
+		}
+		let all = (1 << effect.arity) - 1;
+		let unread = count[0] >> 4;
+		if unread & !all != 0 {
+			return Err(invalid_data("unread flag for a missing operand"));
+		}
+		effect.resolved = all & !unread;
+		for i in 0..operand_count(&effect) {
+			if effect.resolved & 1 << i != 0 {
+				effect.values[i] = read_varint(&mut reader)?;
+			}
+		}
+
+		let mut flags = [0u8];
+		reader.read_exact(&mut flags)?;
+		effect.counted = flags[0] & 16 == 0;
+		if flags[0] & 1 != 0 {
+			effect.write = Some(Write{
+				addr: read_addr(&mut reader)?,
+				old: read_varint(&mut reader)?,
+				new: read_varint(&mut reader)?,
+			});
+		}
+		if flags[0] & 2 != 0 {
+			effect.input = Some(read_varint(&mut reader)?);
+		}
+		if flags[0] & 4 != 0 {
+			effect.output = Some(read_varint(&mut reader)?);
+		}
+		if flags[0] & 8 != 0 {
+			let len = read_addr(&mut reader)?;
+			if len > MAX_MNEMONIC {
+				return Err(invalid_data("mnemonic too long"));
+			}
+			let mut mnemonic = vec![0u8; len];
+			reader.read_exact(&mut mnemonic)?;
+			let mnemonic = String::from_utf8(mnemonic).ok()
+				.filter(|mnemonic| valid_mnemonic(mnemonic))
+				.ok_or(invalid_data("bad mnemonic"))?;
+			effect.mnemonic = intern(&mnemonic);
+		} else {
+			effect.op = u32::try_from(effect.word).ok()
+				.and_then(|w| OpInstruction::from_opcode(w).ok());
+			effect.mnemonic = effect.op.map_or("", |op| op.mnemonic());
+		}
+
+		effects.push(effect);
+	}
+
+	Ok(effects)
+}
+
+//-----------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+	use std::io::ErrorKind as IoErrorKind;
+	use std::sync::Arc;
+
+	use super::{read_binary, TraceFormat, Tracer};
+	use super::super::machine::{Effect, Exec, Machine, State, Write};
+	use super::super::registry::{Flow, OpSpec, Registry};
+
+	// Reads an input, doubles it, outputs it and doubles it again with a
+	// custom instruction.
+	fn machine() -> Machine {
+		let mut registry = Registry::core();
+		registry.register(OpSpec::new(42, "dbl", 2, Some(1), |exec: &mut Exec| {
+			let value = exec.read(0)? * 2;
+			exec.write(1, value)?;
+			Ok(Flow::Next)
+		}));
+
+		let program = vec![3, 13, 1002, 13, 2, 14, 4, 14, 42, 14, 15, 99, 0, 0, 0, 0];
+		Machine::with_registry(program, Arc::new(registry))
+	}
+
+	fn trace(format: TraceFormat) -> (Vec<u8>, Vec<Effect>) {
+		let mut machine = machine();
+		machine.push_input(21);
+		let mut tracer = Tracer::new(Vec::new(), format);
+		let mut effects = Vec::new();
+
+		loop {
+			let state = machine.step().unwrap();
+			if let Some(effect) = machine.last_effect() {
+				tracer.record(effect);
+				effects.push(effect.clone());
+			}
+			if let State::Halted = state {
+				break;
+			}
+		}
+
+		(tracer.finish().unwrap(), effects)
+	}
+
+	#[test]
+	fn binary_round_trip() {
+		let (bytes, mut effects) = trace(TraceFormat::Binary);
+		for effect in effects.iter_mut() {
+			effect.memory_len = 0;
+		}
+		let read = read_binary(&bytes[..]).unwrap();
+		assert_eq!(read, effects);
+
+		assert_eq!(read[0].input, Some(21));
+		assert_eq!(read[0].write, Some(Write{addr: 13, old: 0, new: 21}));
+		assert_eq!(read[2].output, Some(42));
+		assert_eq!((read[3].op, read[3].mnemonic), (None, "dbl"));
+		assert_eq!(read[3].write, Some(Write{addr: 15, old: 0, new: 84}));
+		assert!(read[3].counted && !read[4].counted);
+	}
+
+	#[test]
+	fn corrupt_binary_traces() {
+		let (bytes, _) = trace(TraceFormat::Binary);
+		for len in [4, bytes.len() - 1, bytes.len() - 3].iter() {
+			assert!(read_binary(&bytes[..*len]).is_err());
+		}
+		let error = read_binary(&bytes[..bytes.len() - 1]).unwrap_err();
+		assert_eq!(error.kind(), IoErrorKind::UnexpectedEof);
+
+		let mut bytes = bytes;
+		let at = bytes.windows(3).position(|window| window == b"dbl").unwrap();
+		bytes[at+1] = b'"';
+		assert_eq!(read_binary(&bytes[..]).unwrap_err().kind(), IoErrorKind::InvalidData);
+
+		bytes[0] = b'X';
+		assert_eq!(read_binary(&bytes[..]).unwrap_err().kind(), IoErrorKind::InvalidData);
+	}
+
+	#[test]
+	fn json_lines() {
+		let mut machine = machine();
+		let mut tracer = Tracer::new(Vec::new(), TraceFormat::JsonLines);
+		let mut outputs = Vec::new();
+		let state = tracer.run_with(&mut machine, &mut vec![21].into_iter(), &mut outputs);
+		assert_eq!(state, Ok(State::Halted));
+		assert_eq!(outputs, [42]);
+		assert_eq!(tracer.steps(), 5);
+
+		let (bytes, _) = trace(TraceFormat::JsonLines);
+		assert_eq!(tracer.finish().unwrap(), bytes);
+		assert_eq!(String::from_utf8(bytes).unwrap(), concat!(
+			"{\"step\":0,\"ip\":0,\"word\":3,\"op\":\"in\",\"rb\":0,\"operands\":[13],",
+			"\"write\":{\"addr\":13,\"old\":0,\"new\":21},\"input\":21}\n",
+			"{\"step\":1,\"ip\":2,\"word\":1002,\"op\":\"mul\",\"rb\":0,\"operands\":[21,2,14],",
+			"\"write\":{\"addr\":14,\"old\":0,\"new\":42}}\n",
+			"{\"step\":2,\"ip\":6,\"word\":4,\"op\":\"out\",\"rb\":0,\"operands\":[42],",
+			"\"output\":42}\n",
+			"{\"step\":3,\"ip\":8,\"word\":42,\"op\":\"dbl\",\"rb\":0,\"operands\":[42,15],",
+			"\"write\":{\"addr\":15,\"old\":0,\"new\":84}}\n",
+			"{\"step\":4,\"ip\":11,\"word\":99,\"op\":\"hlt\",\"rb\":0,\"operands\":[]}\n",
+		));
+	}
+
+	#[test]
+	fn untaken_jumps_leave_the_target_unread() {
+		let mut machine = Machine::new(vec![1105, 0, 7, 99]);
+		let mut tracer = Tracer::new(Vec::new(), TraceFormat::JsonLines);
+		assert_eq!(tracer.run(&mut machine), Ok(State::Halted));
+		assert!(String::from_utf8(tracer.finish().unwrap()).unwrap().starts_with(
+			"{\"step\":0,\"ip\":0,\"word\":1105,\"op\":\"jnz\",\"rb\":0,\"operands\":[0,null]}\n"
+		));
+
+		let mut machine = Machine::new(vec![1105, 0, 7, 99]);
+		let mut tracer = Tracer::new(Vec::new(), TraceFormat::Binary);
+		assert_eq!(tracer.run(&mut machine), Ok(State::Halted));
+		let read = read_binary(&tracer.finish().unwrap()[..]).unwrap();
+		assert_eq!((read[0].values, read[0].resolved), ([0, 0, 0], 0b01));
+		assert_eq!(read[1].mnemonic, "hlt");
+	}
+}